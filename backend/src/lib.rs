@@ -1,70 +1,232 @@
 use std::sync::Once;
 
-use anyhow::Result;
 use geo::GeometryCollection;
 use geojson::{Feature, FeatureCollection, GeoJson};
+use serde::Serialize;
 use wkt::ToWkt;
 
 use wasm_bindgen::prelude::*;
+use widths::{Config, Mercator, Pavement};
 
 static START: Once = Once::new();
 
-/// Takes GeoJSON with polygons in WGS84
-#[wasm_bindgen(js_name = findWidths)]
-pub fn find_widths(input: String, raw_cfg: JsValue) -> Result<String, JsValue> {
-    // Panics shouldn't happen, but if they do, console.log them.
-    console_error_panic_hook::set_once();
-    START.call_once(|| {
-        console_log::init_with_level(log::Level::Info).unwrap();
-    });
-
-    let cfg: widths::Config = serde_wasm_bindgen::from_value(raw_cfg)?;
-
-    let mut input_polygons = Vec::new();
-    let mut skeletons = Vec::new();
-    let mut perps = Vec::new();
-    let mut thickened = Vec::new();
-    let mut center_with_width = Vec::new();
-
-    let (pavements, mercator) = widths::utils::read_gj_input(input, &cfg).map_err(err_to_js)?;
-
-    let wkt_input = if pavements.len() == 1 {
-        mercator.to_wgs84(&pavements[0].polygon).wkt_string()
-    } else {
-        String::new()
-    };
-
-    let len = pavements.len();
-    for (idx, mut pavement) in pavements.into_iter().enumerate() {
-        log::info!("Working on input {idx} / {len}");
-        pavement.calculate(&cfg);
-
-        input_polygons.push(pavement.polygon);
-        skeletons.extend(pavement.skeletons);
-        perps.extend(pavement.perp_lines);
-        for (polygon, width1, width2) in pavement.thickened_lines {
-            let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&polygon)));
-            f.set_property("width1", width1);
-            f.set_property("width2", width2);
-            thickened.push(f);
+/// Holds the input and results between calls, so the web app can change the config and only
+/// recalculate what it needs to. Nothing is calculated until `calculate` is called, which works
+/// through the pavements a chunk at a time, so a caller can report progress and stop early.
+#[wasm_bindgen]
+pub struct WidthsSession {
+    mercator: Mercator,
+    cfg: Config,
+    pavements: Vec<Pavement>,
+    // Pavements before this index are up-to-date with cfg
+    next: usize,
+}
+
+#[wasm_bindgen]
+impl WidthsSession {
+    /// Takes polygons in WGS84, as GeoJSON, WKT or hex-encoded WKB
+    #[wasm_bindgen(constructor)]
+    pub fn new(input: String, raw_cfg: JsValue) -> Result<WidthsSession, JsValue> {
+        // Panics shouldn't happen, but if they do, console.log them.
+        console_error_panic_hook::set_once();
+        START.call_once(|| {
+            console_log::init_with_level(log::Level::Info).unwrap();
+        });
+
+        let cfg: Config = serde_wasm_bindgen::from_value(raw_cfg)?;
+        let (pavements, mercator) = widths::utils::read_input(input, &cfg).map_err(err_to_js)?;
+
+        Ok(Self {
+            mercator,
+            cfg,
+            pavements,
+            next: 0,
+        })
+    }
+
+    /// Replaces the config. The next `calculate` only reruns the stages depending on a changed
+    /// setting.
+    #[wasm_bindgen(js_name = setConfig)]
+    pub fn set_config(&mut self, raw_cfg: JsValue) -> Result<(), JsValue> {
+        self.cfg = serde_wasm_bindgen::from_value(raw_cfg)?;
+        self.next = 0;
+        Ok(())
+    }
+
+    /// Takes lines in WGS84, like OSM footways, in any format the input can be. The next
+    /// `calculate` measures along these, clipped to each polygon, instead of finding skeletons.
+    #[wasm_bindgen(js_name = setCenterlines)]
+    pub fn set_centerlines(&mut self, input: String) -> Result<(), JsValue> {
+        let (mut lines, _) = widths::utils::read_linestrings(&input).map_err(err_to_js)?;
+        for line in &mut lines {
+            self.mercator.to_mercator_in_place(line);
+        }
+        widths::assign_centerlines(&mut self.pavements, &lines);
+        self.next = 0;
+        Ok(())
+    }
+
+    /// Brings up to `chunk_size` more pavements up-to-date with the config, then returns true if
+    /// every pavement is done. After each pavement, calls `progress(done, total)`. Stops early if
+    /// `signal` is aborted, which the progress callback may do. Call this repeatedly, yielding to
+    /// the event loop in between, to keep a page responsive and let it abort from elsewhere. An
+    /// abort also returns false, so callers must check the signal themselves to stop looping.
+    /// `chunk_size` must be at least 1.
+    pub fn calculate(
+        &mut self,
+        chunk_size: usize,
+        progress: Option<js_sys::Function>,
+        signal: Option<web_sys::AbortSignal>,
+    ) -> Result<bool, JsValue> {
+        if chunk_size == 0 {
+            return Err(JsValue::from_str("chunk_size must be at least 1"));
+        }
+        let total = self.pavements.len();
+        let end = (self.next + chunk_size).min(total);
+        while self.next < end {
+            if signal.as_ref().is_some_and(|signal| signal.aborted()) {
+                break;
+            }
+            let stages = self.pavements[self.next].calculate(&self.cfg);
+            if !stages.is_empty() {
+                log::info!("Calculated input {} / {total}", self.next);
+            }
+            self.next += 1;
+            if let Some(ref progress) = progress {
+                progress.call2(
+                    &JsValue::NULL,
+                    &JsValue::from(self.next),
+                    &JsValue::from(total),
+                )?;
+            }
+        }
+        Ok(self.next == total)
+    }
+
+    /// Input polygons, with stats
+    #[wasm_bindgen(js_name = getInput)]
+    pub fn get_input(&self) -> Result<JsValue, JsValue> {
+        let mut features = Vec::new();
+        for pavement in &self.pavements {
+            let mut f = self.feature(&pavement.polygon);
+            pavement.set_properties(&mut f);
+            features.push(f);
+        }
+        to_js(&GeoJson::from(features))
+    }
+
+    /// The input as WKT, only if there's exactly one polygon
+    #[wasm_bindgen(js_name = getWktInput)]
+    pub fn get_wkt_input(&self) -> String {
+        if self.pavements.len() == 1 {
+            self.mercator
+                .to_wgs84(&self.pavements[0].polygon)
+                .wkt_string()
+        } else {
+            String::new()
+        }
+    }
+
+    #[wasm_bindgen(js_name = getSkeletons)]
+    pub fn get_skeletons(&self) -> Result<JsValue, JsValue> {
+        let skeletons = GeometryCollection::from_iter(
+            self.pavements
+                .iter()
+                .flat_map(|pavement| pavement.skeletons.clone()),
+        );
+        to_js(&FeatureCollection::from(
+            &self.mercator.to_wgs84(&skeletons),
+        ))
+    }
+
+    #[wasm_bindgen(js_name = getPerps)]
+    pub fn get_perps(&self) -> Result<JsValue, JsValue> {
+        let mut features = Vec::new();
+        for pavement in &self.pavements {
+            for (centerline, samples) in pavement.samples.iter().enumerate() {
+                for (idx, sample) in samples.iter().enumerate() {
+                    let mut f = self.feature(&sample.perp);
+                    sample.set_properties(&mut f, centerline, idx);
+                    features.push(f);
+                }
+            }
+        }
+        to_js(&GeoJson::from(features))
+    }
+
+    #[wasm_bindgen(js_name = getThickened)]
+    pub fn get_thickened(&self) -> Result<JsValue, JsValue> {
+        let mut features = Vec::new();
+        for x in self.pavements.iter().flat_map(|p| &p.thickened_lines) {
+            let mut f = self.feature(&x.polygon);
+            x.set_properties(&mut f);
+            features.push(f);
+        }
+        to_js(&GeoJson::from(features))
+    }
+
+    #[wasm_bindgen(js_name = getCenterWithWidth)]
+    pub fn get_center_with_width(&self) -> Result<JsValue, JsValue> {
+        let mut features = Vec::new();
+        for pavement in &self.pavements {
+            for (idx, x) in pavement.center_with_width.iter().enumerate() {
+                let mut f = self.feature(&x.linestring);
+                x.set_properties(
+                    &mut f,
+                    pavement.cross_sections.get(idx).map_or(&[], Vec::as_slice),
+                );
+                features.push(f);
+            }
+        }
+        to_js(&GeoJson::from(features))
+    }
+
+    #[wasm_bindgen(js_name = getPinchPoints)]
+    pub fn get_pinch_points(&self) -> Result<JsValue, JsValue> {
+        let mut features = Vec::new();
+        for pinch in self.pavements.iter().flat_map(|p| &p.pinch_points) {
+            let mut f = self.feature(&geo::Point::from(pinch.pt));
+            pinch.set_properties(&mut f, &self.mercator);
+            features.push(f);
         }
-        for (ls, min, max) in pavement.center_with_width {
-            let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&ls)));
-            f.set_property("min_width", min);
-            f.set_property("max_width", max);
-            center_with_width.push(f);
+        to_js(&GeoJson::from(features))
+    }
+
+    /// One width profile per center line of every input, each with an `input` index
+    #[wasm_bindgen(js_name = getWidthProfiles)]
+    pub fn get_width_profiles(&self) -> Result<JsValue, JsValue> {
+        let mut profiles = Vec::new();
+        for (input_idx, pavement) in self.pavements.iter().enumerate() {
+            for profile in pavement.width_profiles() {
+                let mut json = serde_json::to_value(profile).map_err(err_to_js)?;
+                json["input"] = input_idx.into();
+                profiles.push(json);
+            }
         }
+        to_js(&profiles)
+    }
+
+    /// The stats for each input, or null if an input had no samples
+    #[wasm_bindgen(js_name = getStats)]
+    pub fn get_stats(&self) -> Result<JsValue, JsValue> {
+        let stats: Vec<_> = self.pavements.iter().map(|p| p.stats.as_ref()).collect();
+        to_js(&stats)
     }
+}
+
+impl WidthsSession {
+    fn feature<G>(&self, geometry: &G) -> Feature
+    where
+        G: geo::MapCoords<f64, f64, Output = G>,
+        for<'a> geojson::Geometry: From<&'a G>,
+    {
+        Feature::from(geojson::Geometry::from(&self.mercator.to_wgs84(geometry)))
+    }
+}
 
-    let json = serde_json::json!({
-        "input": FeatureCollection::from(&mercator.to_wgs84(&GeometryCollection::from_iter(input_polygons))),
-        "skeletons": FeatureCollection::from(&mercator.to_wgs84(&GeometryCollection::from_iter(skeletons))),
-        "perps": FeatureCollection::from(&mercator.to_wgs84(&GeometryCollection::from_iter(perps))),
-        "thickened": GeoJson::from(thickened),
-        "center_with_width": GeoJson::from(center_with_width),
-        "wkt_input": wkt_input,
-    });
-    Ok(json.to_string())
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    // Plain JS objects, not Maps
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
 }
 
 fn err_to_js<E: std::fmt::Display>(err: E) -> JsValue {
@@ -1,40 +1,125 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use gdal::{vector::LayerAccess, Dataset};
-use geo::{Area, EuclideanLength, Geometry, GeometryCollection};
+use geo::{Area, EuclideanLength, Geometry, GeometryCollection, LineString};
 use geojson::{Feature, FeatureCollection, GeoJson};
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 
 use widths::{
-    utils::{read_gj_input, to_mercator},
-    Config, Mercator, Pavement,
+    assign_centerlines, conflate, merge_pavements, split_pavements, suggest_osm_tags,
+    utils::{read_input, read_linestrings, read_property, read_wkb_input, to_mercator},
+    Config, ConflationConfig, Mercator, MergeConfig, Pavement, SplitConfig,
 };
 
 fn main() -> Result<()> {
     env_logger::init();
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        println!("Call with a .geojson or .gpkg file in WGS84");
+    let mut csv = false;
+    let mut centerlines = None;
+    let mut links = None;
+    let mut merge = false;
+    let mut merge_by = None;
+    let mut split_lines = None;
+    let mut split_junctions = false;
+    let mut inputs = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--csv" {
+            csv = true;
+        } else if arg == "--centerlines" {
+            let Some(path) = args.next() else {
+                print_usage();
+                std::process::exit(1);
+            };
+            centerlines = Some(path);
+        } else if arg == "--links" {
+            let Some(path) = args.next() else {
+                print_usage();
+                std::process::exit(1);
+            };
+            links = Some(path);
+        } else if arg == "--merge" {
+            merge = true;
+        } else if arg == "--merge-by" {
+            let Some(key) = args.next() else {
+                print_usage();
+                std::process::exit(1);
+            };
+            merge_by = Some(key);
+        } else if arg == "--split-lines" {
+            let Some(path) = args.next() else {
+                print_usage();
+                std::process::exit(1);
+            };
+            split_lines = Some(path);
+        } else if arg == "--split-junctions" {
+            split_junctions = true;
+        } else {
+            inputs.push(arg);
+        }
+    }
+    if inputs.len() != 1 {
+        print_usage();
         std::process::exit(1);
     }
+    let input = &inputs[0];
+    if merge_by.is_some() && (input.ends_with(".gpkg") || input.ends_with(".wkb")) {
+        bail!(
+            "--merge-by only works with GeoJSON input, since the other formats have no properties"
+        );
+    }
 
     let cfg = Config::default();
-    let (pavements, mercator) = if args[1].ends_with(".geojson") {
-        read_gj_input(std::fs::read_to_string(&args[1])?, &cfg)?
-    } else if args[1].ends_with(".gpkg") {
+    let (mut pavements, mercator) = if input.ends_with(".gpkg") {
         // TODO Take a flag to decide which one, or do the filtering elsewhere?
         //read_gpkg_input("../test_input/large.gpkg", "Roadside", &cfg)?
         read_gpkg_input("../test_input/large.gpkg", "Road Or Track", &cfg)?
+    } else if input.ends_with(".wkb") {
+        read_wkb_input(&std::fs::read(input)?, &cfg)?
     } else {
-        println!("Call with a .geojson or .gpkg file in WGS84");
-        std::process::exit(1);
+        read_input(std::fs::read_to_string(input)?, &cfg)?
     };
+    if merge || merge_by.is_some() {
+        let groups = match merge_by {
+            Some(key) => Some(read_property(&std::fs::read_to_string(input)?, &key)?),
+            None => None,
+        };
+        pavements = merge_pavements(pavements, groups.as_deref(), &MergeConfig::default(), &cfg);
+    }
+    if let Some(path) = centerlines {
+        let (mut lines, _) = read_linestrings(&std::fs::read_to_string(path)?)?;
+        for line in &mut lines {
+            mercator.to_mercator_in_place(line);
+        }
+        assign_centerlines(&mut pavements, &lines);
+    }
+    if split_lines.is_some() || split_junctions {
+        let mut lines = Vec::new();
+        if let Some(path) = split_lines {
+            lines = read_linestrings(&std::fs::read_to_string(path)?)?.0;
+            for line in &mut lines {
+                mercator.to_mercator_in_place(line);
+            }
+        }
+        let mut split_cfg = SplitConfig::default();
+        if !split_junctions {
+            split_cfg.junction_radius = None;
+        }
+        write_unsplit("output/unsplit_polygons.geojson", &pavements, &mercator)?;
+        pavements = split_pavements(pavements, &lines, &split_cfg, &cfg);
+    }
+
+    let repaired = pavements.iter().filter(|p| !p.repairs.is_empty()).count();
+    if repaired > 0 {
+        println!("Repaired {repaired} invalid polygons. See the repaired_* properties in output/input_polygons.geojson");
+    }
 
     let mut input_polygons = Vec::new();
     let mut skeletons = Vec::new();
     let mut perps = Vec::new();
     let mut thickened = Vec::new();
     let mut center_with_width = Vec::new();
+    let mut pinch_points = Vec::new();
+    let mut width_profiles = Vec::new();
 
     let progress = ProgressBar::new(pavements.len() as u64).with_style(ProgressStyle::with_template(
         "[{elapsed_precise}] [{wide_bar:.cyan/blue}] {human_pos}/{human_len} ({per_sec}, {eta})").unwrap());
@@ -49,27 +134,86 @@ fn main() -> Result<()> {
     // TODO Long lag here, it might be the collect part?
     println!("Generating output");
 
-    for pavement in pavements {
-        input_polygons.push(pavement.polygon);
+    if let Some(path) = links {
+        let (mut links, source_ids) = read_linestrings(&std::fs::read_to_string(path)?)?;
+        for link in &mut links {
+            mercator.to_mercator_in_place(link);
+        }
+        write_links(
+            "output/links.geojson",
+            &links,
+            &source_ids,
+            &pavements,
+            &mercator,
+        )?;
+        write_osm_tags(
+            "output/osm_tags.geojson",
+            &links,
+            &source_ids,
+            &pavements,
+            &mercator,
+        )?;
+    }
+
+    if csv {
+        return write_csv("output/samples.csv", &pavements, &mercator, &cfg);
+    }
+
+    for (input_idx, pavement) in pavements.into_iter().enumerate() {
+        let mut f = Feature::from(geojson::Geometry::from(
+            &mercator.to_wgs84(&pavement.polygon),
+        ));
+        pavement.set_properties(&mut f);
+        input_polygons.push(f);
+        for profile in pavement.width_profiles() {
+            let mut json = serde_json::to_value(profile)?;
+            json["input"] = input_idx.into();
+            width_profiles.push(json);
+        }
         skeletons.extend(pavement.skeletons);
-        perps.extend(pavement.perp_lines);
-        for (polygon, width1, width2) in pavement.thickened_lines {
-            let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&polygon)));
-            f.set_property("width1", width1);
-            f.set_property("width2", width2);
+        for (centerline, samples) in pavement.samples.into_iter().enumerate() {
+            for (idx, sample) in samples.into_iter().enumerate() {
+                let mut f =
+                    Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&sample.perp)));
+                sample.set_properties(&mut f, centerline, idx);
+                perps.push(f);
+            }
+        }
+        for x in pavement.thickened_lines {
+            let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&x.polygon)));
+            x.set_properties(&mut f);
             thickened.push(f);
         }
-        for (ls, min, max) in pavement.center_with_width {
-            let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&ls)));
-            f.set_property("min_width", min);
-            f.set_property("max_width", max);
+        for (idx, x) in pavement.center_with_width.into_iter().enumerate() {
+            let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&x.linestring)));
+            x.set_properties(
+                &mut f,
+                pavement.cross_sections.get(idx).map_or(&[], Vec::as_slice),
+            );
             center_with_width.push(f);
         }
+        for pinch in pavement.pinch_points {
+            let mut f = Feature::from(geojson::Geometry::from(
+                &mercator.to_wgs84(&geo::Point::from(pinch.pt)),
+            ));
+            pinch.set_properties(&mut f, &mercator);
+            pinch_points.push(f);
+        }
     }
 
-    dump_gj("output/input_polygons.geojson", &mercator, input_polygons)?;
+    std::fs::write(
+        "output/input_polygons.geojson",
+        serde_json::to_string(&GeoJson::from(input_polygons))?,
+    )?;
+    println!("Wrote output/input_polygons.geojson");
+
     dump_gj("output/skeletons.geojson", &mercator, skeletons)?;
-    dump_gj("output/perps.geojson", &mercator, perps)?;
+
+    std::fs::write(
+        "output/perps.geojson",
+        serde_json::to_string(&GeoJson::from(perps))?,
+    )?;
+    println!("Wrote output/perps.geojson");
 
     std::fs::write(
         "output/thickened.geojson",
@@ -83,6 +227,141 @@ fn main() -> Result<()> {
     )?;
     println!("Wrote output/center_with_width.geojson");
 
+    std::fs::write(
+        "output/pinch_points.geojson",
+        serde_json::to_string(&GeoJson::from(pinch_points))?,
+    )?;
+    println!("Wrote output/pinch_points.geojson");
+
+    std::fs::write(
+        "output/width_profiles.json",
+        serde_json::to_string(&width_profiles)?,
+    )?;
+    println!("Wrote output/width_profiles.json");
+
+    Ok(())
+}
+
+fn print_usage() {
+    println!("Call with a file in WGS84: .gpkg, binary .wkb, or GeoJSON, WKT or hex-encoded WKB");
+    println!("Pass --centerlines with a file of lines, like OSM footways, to measure along them instead of finding skeletons");
+    println!("Pass --links with a file of lines, like OSM ways, to write width stats for each one to output/links.geojson, and suggested OSM width tags to output/osm_tags.geojson");
+    println!("Pass --merge to dissolve touching polygons first, or --merge-by with a property name to only merge polygons with the same value. Polygons without the property aren't merged, and properties are only read from GeoJSON.");
+    println!("Pass --split-lines with a file of lines, like kerbs or crossings, to cut polygons into pieces along them, and --split-junctions to also cut them where center lines meet. The polygons before cutting are written to output/unsplit_polygons.geojson, and each piece's parent property matches one of them.");
+    println!("Pass --csv to write one row per sample to output/samples.csv, instead of GeoJSON");
+}
+
+/// Writes one row per width sample, for loading width profiles into spreadsheets
+fn write_csv(path: &str, pavements: &[Pavement], mercator: &Mercator, cfg: &Config) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "source_id",
+        "centerline",
+        "sample",
+        "chainage",
+        "lon",
+        "lat",
+        "width",
+        "left",
+        "right",
+        "unbounded",
+        "category",
+    ])?;
+    for pavement in pavements {
+        for (centerline, samples) in pavement.samples.iter().enumerate() {
+            for (idx, sample) in samples.iter().enumerate() {
+                let pt = mercator.pt_to_wgs84(sample.pt);
+                writer.serialize((
+                    pavement.source_ids.join(";"),
+                    centerline,
+                    idx,
+                    sample.dist_along,
+                    pt.x,
+                    pt.y,
+                    sample.width(),
+                    sample.left,
+                    sample.right,
+                    sample.unbounded,
+                    cfg.width_bands.categorize(sample.width()).key(),
+                ))?;
+            }
+        }
+    }
+    writer.flush()?;
+    println!("Wrote {path}");
+    Ok(())
+}
+
+/// Writes the pavements before split_pavements, each with a parent property to match the pieces
+/// cut from it
+fn write_unsplit(path: &str, pavements: &[Pavement], mercator: &Mercator) -> Result<()> {
+    let mut features = Vec::new();
+    for (idx, pavement) in pavements.iter().enumerate() {
+        let mut f = Feature::from(geojson::Geometry::from(
+            &mercator.to_wgs84(&pavement.polygon),
+        ));
+        f.set_property("parent", idx);
+        if !pavement.source_ids.is_empty() {
+            f.set_property("source_id", pavement.source_ids.join(";"));
+        }
+        features.push(f);
+    }
+    std::fs::write(path, serde_json::to_string(&GeoJson::from(features))?)?;
+    println!("Wrote {path}");
+    Ok(())
+}
+
+/// Matches widths to each link of a line network, and writes the links with their stats
+fn write_links(
+    path: &str,
+    links: &[LineString],
+    source_ids: &[String],
+    pavements: &[Pavement],
+    mercator: &Mercator,
+) -> Result<()> {
+    let results = conflate(pavements, links, &ConflationConfig::default());
+    let mut features = Vec::new();
+    for ((link, source_id), result) in links.iter().zip(source_ids).zip(results) {
+        let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(link)));
+        f.set_property("source_id", source_id.clone());
+        result.set_properties(&mut f);
+        features.push(f);
+    }
+    std::fs::write(path, serde_json::to_string(&GeoJson::from(features))?)?;
+    println!("Wrote {path}");
+    Ok(())
+}
+
+/// Writes the links treated as OSM ways with suggested width tags as properties, so they can be
+/// reviewed in an editor like JOSM. Ways without any suggestion are left out.
+fn write_osm_tags(
+    path: &str,
+    ways: &[LineString],
+    source_ids: &[String],
+    pavements: &[Pavement],
+    mercator: &Mercator,
+) -> Result<()> {
+    let mut features: Vec<Feature> = Vec::new();
+    let mut last_way = None;
+    for suggestion in suggest_osm_tags(pavements, ways, &ConflationConfig::default()) {
+        // Suggestions are grouped by way
+        if last_way != Some(suggestion.way) {
+            last_way = Some(suggestion.way);
+            let mut f = Feature::from(geojson::Geometry::from(
+                &mercator.to_wgs84(&ways[suggestion.way]),
+            ));
+            f.set_property("source_id", source_ids[suggestion.way].clone());
+            features.push(f);
+        }
+        let f = features.last_mut().unwrap();
+        f.set_property(suggestion.key, suggestion.value);
+        f.set_property(
+            format!("{}:confidence", suggestion.key),
+            suggestion.confidence,
+        );
+    }
+    std::fs::write(path, serde_json::to_string(&GeoJson::from(features))?)?;
+    println!("Wrote {path}");
     Ok(())
 }
 
@@ -104,6 +383,7 @@ fn read_gpkg_input(
     cfg: &Config,
 ) -> Result<(Vec<Pavement>, Mercator)> {
     let mut polygons = Vec::new();
+    let mut source_ids = Vec::new();
     let dataset = Dataset::open(filename)?;
     // Assume only one layer
     let mut layer = dataset.layer(0)?;
@@ -123,9 +403,13 @@ fn read_gpkg_input(
             continue;
         };
         polygons.push(polygon);
+        source_ids.push(feature.fid().map(|fid| fid.to_string()));
     }
 
     let (mut pavements, mercator) = to_mercator(polygons, cfg);
+    for (pavement, id) in pavements.iter_mut().zip(source_ids) {
+        pavement.source_ids = id.into_iter().collect();
+    }
 
     // Filter out junctions, only keep roads
     if descriptive_group == "Road Or Track" {
@@ -64,5 +64,6 @@ const layerZorder = [
   "thickened",
   "center-with-width",
   "center-endpoints",
+  "pinch-points",
 ];
 // TODO PolygonToolLayer in a library needs to be able to do this kind of thing
@@ -0,0 +1,150 @@
+//! Times each stage of the pipeline separately, on the web app's test cases and some large
+//! synthetic polygons. Run with `cargo bench`; pass a filter like `cargo bench -- perps` to run one
+//! stage.
+
+use anyhow::{anyhow, Result};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use geo::{LineString, Polygon};
+use widths::synthetic::SyntheticCase;
+use widths::{utils::to_mercator, Config, Pavement};
+use wkt::TryFromWkt;
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+/// The output of each stage, so later stages can be timed alone
+struct Input {
+    name: String,
+    polygon: Polygon,
+    // Before join_skeletons
+    raw_skeletons: Vec<LineString>,
+    pavement: Pavement,
+}
+
+fn inputs(cfg: &Config) -> Result<Vec<Input>> {
+    let mut polygons = Vec::new();
+    for (name, wkt) in common::read_test_cases()? {
+        let polygon = Polygon::try_from_wkt_str(&wkt).map_err(|err| anyhow!("{err}"))?;
+        let (pavements, _) = to_mercator(vec![polygon], cfg);
+        polygons.push((name, pavements.into_iter().next().unwrap().polygon));
+    }
+    // Much longer than any real test case
+    for case in [
+        SyntheticCase::straight(5000.0, 3.0),
+        SyntheticCase::curved(500.0, 300.0, 4.0),
+        SyntheticCase::l_shape(2000.0, 2.5),
+        SyntheticCase::tapering(5000.0, 6.0, 1.5),
+    ] {
+        polygons.push((format!("large {}", case.name), case.polygon));
+    }
+
+    let mut no_join = Config::default();
+    no_join.join_skeletons = false;
+
+    let mut results = Vec::new();
+    for (name, polygon) in polygons {
+        let mut unjoined = Pavement::new(polygon.clone(), &no_join);
+        unjoined.skeletonize(&no_join);
+
+        let mut pavement = Pavement::new(polygon.clone(), cfg);
+        pavement.skeletonize(cfg);
+        pavement.make_perp_lines(cfg);
+
+        results.push(Input {
+            name,
+            polygon,
+            raw_skeletons: unjoined.skeletons,
+            pavement,
+        });
+    }
+    Ok(results)
+}
+
+fn benchmark_stages(c: &mut Criterion) {
+    let cfg = Config::default();
+    let inputs = inputs(&cfg).unwrap();
+
+    let mut group = c.benchmark_group("skeletonize");
+    for input in &inputs {
+        group.bench_function(&input.name, |b| {
+            b.iter_batched(
+                || Pavement::new(input.polygon.clone(), &cfg),
+                |mut pavement| pavement.skeletonize(&cfg),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("join");
+    for input in &inputs {
+        group.bench_function(&input.name, |b| {
+            b.iter_batched(
+                || input.raw_skeletons.clone(),
+                widths::join_lines::join_linestrings,
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("step_along_line");
+    for input in &inputs {
+        group.bench_function(&input.name, |b| {
+            b.iter(|| {
+                for skeleton in &input.pavement.skeletons {
+                    for step in widths::step_along_line::step_along_line(
+                        skeleton,
+                        cfg.make_perps_step_size.unwrap(),
+                    ) {
+                        black_box(step);
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("perps");
+    for input in &inputs {
+        group.bench_function(&input.name, |b| {
+            b.iter_batched(
+                || {
+                    let mut pavement = Pavement::new(input.polygon.clone(), &cfg);
+                    pavement.skeletons = input.pavement.skeletons.clone();
+                    pavement
+                },
+                |mut pavement| pavement.make_perp_lines(&cfg),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("split");
+    for input in &inputs {
+        group.bench_function(&input.name, |b| {
+            b.iter(|| {
+                for (centerline, (skeleton, samples)) in input
+                    .pavement
+                    .skeletons
+                    .iter()
+                    .zip(&input.pavement.samples)
+                    .enumerate()
+                {
+                    black_box(widths::split_line::split(
+                        skeleton,
+                        centerline,
+                        samples,
+                        cfg.width_granularity,
+                        &cfg.width_bands,
+                    ));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_stages);
+criterion_main!(benches);
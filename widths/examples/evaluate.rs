@@ -0,0 +1,57 @@
+//! Measures how accurately widths are calculated, using synthetic polygons with known widths.
+//!
+//! Usage: cargo run --release --example evaluate [config.json]
+//!
+//! Without a config file, Config::default() is used. Compare the output before and after changing
+//! the config or the algorithm.
+
+use anyhow::Result;
+use widths::synthetic::SyntheticCase;
+use widths::Config;
+
+fn main() -> Result<()> {
+    let cfg = match std::env::args().nth(1) {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => Config::default(),
+    };
+
+    println!(
+        "{:<12} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "case", "samples", "bias", "mean abs", "rmse", "max abs", "skel ratio"
+    );
+    let mut total_samples = 0;
+    let mut total_abs_error = 0.0;
+    let mut total_squared_error = 0.0;
+    for case in SyntheticCase::all() {
+        let Some(eval) = case.evaluate(&cfg) else {
+            println!("{:<12} no samples", case.name);
+            continue;
+        };
+        println!(
+            "{:<12} {:>8} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>10.2}",
+            case.name,
+            eval.samples,
+            eval.mean_error,
+            eval.mean_abs_error,
+            eval.rmse,
+            eval.max_abs_error,
+            eval.skeleton_length_ratio
+        );
+        total_samples += eval.samples;
+        total_abs_error += eval.mean_abs_error * eval.samples as f64;
+        total_squared_error += eval.rmse.powi(2) * eval.samples as f64;
+    }
+
+    if total_samples > 0 {
+        let n = total_samples as f64;
+        println!(
+            "{:<12} {:>8} {:>10} {:>10.3} {:>10.3}",
+            "overall",
+            total_samples,
+            "",
+            total_abs_error / n,
+            (total_squared_error / n).sqrt()
+        );
+    }
+    Ok(())
+}
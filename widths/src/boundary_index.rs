@@ -0,0 +1,107 @@
+use geo::{Coord, Intersects, Line, Polygon, Rect};
+use rstar::{RTree, RTreeObject, SelectionFunction, AABB};
+
+/// An R-tree of every line along a polygon's exterior and holes, so lines crossing the polygon
+/// don't need to check every edge
+pub struct BoundaryIndex {
+    tree: RTree<BoundaryLine>,
+}
+
+struct BoundaryLine(Line);
+
+impl RTreeObject for BoundaryLine {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_corners(self.0.start.into(), self.0.end.into())
+    }
+}
+
+// Only descends into parts of the tree whose bounding box the line crosses. For a long diagonal
+// line, that prunes much more than just checking its bounding box.
+struct CrossedBy(Line);
+
+impl SelectionFunction<BoundaryLine> for CrossedBy {
+    fn should_unpack_parent(&self, envelope: &AABB<[f64; 2]>) -> bool {
+        let lower: Coord = envelope.lower().into();
+        let upper: Coord = envelope.upper().into();
+        Rect::new(lower, upper).intersects(&self.0)
+    }
+
+    fn should_unpack_leaf(&self, leaf: &BoundaryLine) -> bool {
+        self.should_unpack_parent(&leaf.envelope())
+    }
+}
+
+impl BoundaryIndex {
+    pub fn new(polygon: &Polygon) -> Self {
+        let lines = vec![polygon.exterior()]
+            .into_iter()
+            .chain(polygon.interiors())
+            .flat_map(|boundary| boundary.lines())
+            .map(BoundaryLine)
+            .collect();
+        Self {
+            tree: RTree::bulk_load(lines),
+        }
+    }
+
+    /// Returns every boundary line that might intersect `line`, in no particular order
+    pub fn candidates(&self, line: Line) -> impl Iterator<Item = Line> + '_ {
+        self.tree
+            .locate_with_selection_function(CrossedBy(line))
+            .map(|boundary_line| boundary_line.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::LineString;
+
+    #[test]
+    fn finds_every_crossed_line() {
+        // A 10x10 square with a 2x2 hole in the middle
+        let polygon = Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 10.0),
+                (0.0, 10.0),
+                (0.0, 0.0),
+            ]),
+            vec![LineString::from(vec![
+                (4.0, 4.0),
+                (6.0, 4.0),
+                (6.0, 6.0),
+                (4.0, 6.0),
+                (4.0, 4.0),
+            ])],
+        );
+        let index = BoundaryIndex::new(&polygon);
+
+        let line = Line::new((-1.0, 5.0), (11.0, 5.0));
+        let mut expected: Vec<Line> = polygon
+            .exterior()
+            .lines()
+            .chain(polygon.interiors()[0].lines())
+            .filter(|x| x.intersects(&line))
+            .collect();
+        let mut actual: Vec<Line> = index.candidates(line).collect();
+        let sort = |lines: &mut Vec<Line>| {
+            lines.sort_by(|a, b| {
+                (a.start.x, a.start.y, a.end.x, a.end.y)
+                    .partial_cmp(&(b.start.x, b.start.y, b.end.x, b.end.y))
+                    .unwrap()
+            })
+        };
+        sort(&mut expected);
+        sort(&mut actual);
+        assert_eq!(expected.len(), 4);
+        assert_eq!(actual, expected);
+
+        // Passes through the square's bounding box, but misses the hole entirely
+        let line = Line::new((1.0, -1.0), (1.0, 11.0));
+        assert_eq!(index.candidates(line).count(), 2);
+    }
+}
@@ -0,0 +1,84 @@
+use geo::{BoundingRect, Intersects, LineString};
+use rstar::{primitives::GeomWithData, primitives::Rectangle, RTree, AABB};
+
+use crate::Pavement;
+
+/// Gives each pavement every line crossing its polygon, like OSM footways, to use as its center
+/// lines instead of skeletons. Pavements without any lines will have no results. Everything must
+/// be in Mercator.
+pub fn assign_centerlines(pavements: &mut [Pavement], lines: &[LineString]) {
+    let tree = RTree::bulk_load(
+        pavements
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, pavement)| {
+                let bbox = pavement.polygon.bounding_rect()?;
+                Some(GeomWithData::new(
+                    Rectangle::<[f64; 2]>::from_corners(bbox.min().into(), bbox.max().into()),
+                    idx,
+                ))
+            })
+            .collect(),
+    );
+
+    let mut per_pavement = vec![Vec::new(); pavements.len()];
+    for line in lines {
+        let Some(bbox) = line.bounding_rect() else {
+            continue;
+        };
+        let envelope = AABB::from_corners(bbox.min().into(), bbox.max().into());
+        for obj in tree.locate_in_envelope_intersecting(&envelope) {
+            if pavements[obj.data].polygon.intersects(line) {
+                per_pavement[obj.data].push(line.clone());
+            }
+        }
+    }
+
+    for (pavement, lines) in pavements.iter_mut().zip(per_pavement) {
+        pavement.set_centerlines(lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use geo::Polygon;
+
+    fn square(x1: f64, x2: f64) -> Polygon {
+        Polygon::new(
+            LineString::from(vec![(x1, 0.0), (x2, 0.0), (x2, 4.0), (x1, 4.0), (x1, 0.0)]),
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn lines_are_clipped_to_each_pavement() {
+        let cfg = Config::default();
+        let mut pavements = vec![
+            Pavement::new(square(0.0, 10.0), &cfg),
+            Pavement::new(square(20.0, 30.0), &cfg),
+            Pavement::new(square(40.0, 50.0), &cfg),
+        ];
+        // Crosses the first two squares, but not the third
+        let line = LineString::from(vec![(-5.0, 2.0), (35.0, 2.0)]);
+        assign_centerlines(&mut pavements, &[line]);
+
+        for pavement in &mut pavements {
+            pavement.calculate(&cfg);
+        }
+        assert_eq!(
+            pavements[0].skeletons,
+            vec![LineString::from(vec![(0.0, 2.0), (10.0, 2.0)])]
+        );
+        assert_eq!(
+            pavements[1].skeletons,
+            vec![LineString::from(vec![(20.0, 2.0), (30.0, 2.0)])]
+        );
+        assert!(pavements[2].skeletons.is_empty());
+
+        // Samples every 5m, all 4m wide
+        let widths: Vec<f64> = pavements[0].samples[0].iter().map(|s| s.width()).collect();
+        assert_eq!(widths, vec![4.0, 4.0]);
+    }
+}
@@ -0,0 +1,345 @@
+use geo::{
+    Closest, ClosestPoint, Coord, EuclideanLength, Line, LineLocatePoint, LineString, Point,
+};
+use geojson::Feature;
+use rstar::{primitives::GeomWithData, PointDistance, RTree};
+use serde::{Deserialize, Serialize};
+
+use crate::stats::{weighted_percentiles, Interval};
+use crate::Pavement;
+
+/// How to match center lines to links in `conflate`
+#[derive(Clone, Deserialize)]
+pub struct ConflationConfig {
+    // How far in meters the center line can be from a link
+    pub max_distance: f64,
+    // How many degrees the center line's direction can differ from the link's. Which way either
+    // one points doesn't matter.
+    pub max_angle: f64,
+}
+
+impl Default for ConflationConfig {
+    fn default() -> Self {
+        Self {
+            max_distance: 10.0,
+            max_angle: 30.0,
+        }
+    }
+}
+
+/// The widths measured along one link of a line network
+#[derive(Serialize)]
+pub struct LinkWidths {
+    // Index into the links passed to conflate
+    pub link: usize,
+    // In meters
+    pub length: f64,
+    // The fraction of the link that some center line matched, from 0 to 1
+    pub coverage: f64,
+    // None if nothing matched
+    pub stats: Option<LinkStats>,
+}
+
+#[derive(Serialize)]
+pub struct LinkStats {
+    // Length of center line matched to the link, in meters
+    pub matched_length: f64,
+    pub min_width: f64,
+    pub max_width: f64,
+    // Both weighted by the length of center line between samples
+    pub mean_width: f64,
+    pub std_dev_width: f64,
+    // (percentile, width), also weighted by length
+    pub percentiles: Vec<(usize, f64)>,
+}
+
+type LinkSegment = GeomWithData<rstar::primitives::Line<[f64; 2]>, usize>;
+
+/// Transfers widths onto links of a line network, like OSM ways. The center line between each
+/// pair of consecutive samples (the pieces center_with_width is made from) is matched to the
+/// closest link that's nearby and running the same direction, whether the link runs through the
+/// measured area or alongside it. One center_with_width piece running alongside two links is
+/// split between them. Everything must be in Mercator. Returns one result per link, in order.
+pub fn conflate(
+    pavements: &[Pavement],
+    links: &[LineString],
+    cfg: &ConflationConfig,
+) -> Vec<LinkWidths> {
+    let mut intervals: Vec<Vec<Interval>> = links.iter().map(|_| Vec::new()).collect();
+    // Ranges of each link, as fractions of its length
+    let mut covered: Vec<Vec<(f64, f64)>> = links.iter().map(|_| Vec::new()).collect();
+    for m in match_intervals(pavements, links, cfg) {
+        intervals[m.link].push(m.interval);
+        covered[m.link].push(m.covered);
+    }
+
+    links
+        .iter()
+        .zip(intervals)
+        .zip(covered)
+        .enumerate()
+        .map(|(idx, ((link, mut intervals), covered))| LinkWidths {
+            link: idx,
+            length: link.euclidean_length(),
+            coverage: union_length(covered),
+            stats: LinkStats::new(&mut intervals),
+        })
+        .collect()
+}
+
+impl LinkWidths {
+    /// Adds all results as properties of a GeoJSON feature
+    pub fn set_properties(&self, f: &mut Feature) {
+        f.set_property("link", self.link);
+        f.set_property("length", self.length);
+        f.set_property("coverage", self.coverage);
+        if let Some(ref stats) = self.stats {
+            f.set_property("matched_length", stats.matched_length);
+            f.set_property("min_width", stats.min_width);
+            f.set_property("max_width", stats.max_width);
+            f.set_property("mean_width", stats.mean_width);
+            f.set_property("std_dev_width", stats.std_dev_width);
+            for (pct, width) in &stats.percentiles {
+                f.set_property(format!("p{pct}_width"), *width);
+            }
+        }
+    }
+}
+
+impl LinkStats {
+    pub(crate) fn new(intervals: &mut [Interval]) -> Option<Self> {
+        if intervals.is_empty() {
+            return None;
+        }
+        let matched_length: f64 = intervals.iter().map(|i| i.length).sum();
+        let widths = || intervals.iter().flat_map(|i| [i.width1, i.width2]);
+        let min_width = widths().fold(f64::MAX, f64::min);
+        let max_width = widths().fold(f64::MIN, f64::max);
+        let mean_width = intervals
+            .iter()
+            .map(|i| i.length * i.mean_width())
+            .sum::<f64>()
+            / matched_length;
+        let variance = intervals
+            .iter()
+            .map(|i| i.length * (i.mean_width() - mean_width).powi(2))
+            .sum::<f64>()
+            / matched_length;
+        Some(Self {
+            matched_length,
+            min_width,
+            max_width,
+            mean_width,
+            std_dev_width: variance.sqrt(),
+            percentiles: weighted_percentiles(intervals),
+        })
+    }
+}
+
+/// The center line between two samples, matched to a link
+pub(crate) struct Match {
+    pub link: usize,
+    pub interval: Interval,
+    // The range of the link alongside the interval, as fractions of its length
+    pub covered: (f64, f64),
+    pub side: Side,
+}
+
+/// Where the measured area is relative to a link
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Side {
+    // The link runs through the area, so the width is the link's own
+    Along,
+    Left,
+    Right,
+}
+
+/// Matches the center line between each pair of consecutive samples to the closest link that's
+/// nearby and running the same direction
+pub(crate) fn match_intervals(
+    pavements: &[Pavement],
+    links: &[LineString],
+    cfg: &ConflationConfig,
+) -> Vec<Match> {
+    let tree: RTree<LinkSegment> = RTree::bulk_load(
+        links
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, link)| {
+                link.lines().map(move |line| {
+                    GeomWithData::new(
+                        rstar::primitives::Line::new(line.start.into(), line.end.into()),
+                        idx,
+                    )
+                })
+            })
+            .collect(),
+    );
+
+    let mut matches = Vec::new();
+    for samples in pavements.iter().flat_map(|p| &p.samples) {
+        for pair in samples.windows(2) {
+            let piece = Line::new(pair[0].pt, pair[1].pt);
+            let Some((link, segment)) = best_link(&tree, piece, cfg) else {
+                continue;
+            };
+
+            // Does the link pass between the two sides of the area?
+            let midpoint = (piece.start + piece.end) / 2.0;
+            let closest = match segment.closest_point(&Point::from(midpoint)) {
+                Closest::Intersection(pt) | Closest::SinglePoint(pt) => pt.into(),
+                Closest::Indeterminate => segment.start,
+            };
+            let left = (pair[0].left + pair[1].left) / 2.0;
+            let right = (pair[0].right + pair[1].right) / 2.0;
+            let link_offset = side_of_line(piece, closest);
+            let side = if link_offset >= -left && link_offset <= right {
+                Side::Along
+            } else if side_of_line(segment, midpoint) < 0.0 {
+                Side::Left
+            } else {
+                Side::Right
+            };
+
+            let locate = |pt| {
+                links[link]
+                    .line_locate_point(&Point::from(pt))
+                    .unwrap_or(0.0)
+            };
+            let (fraction1, fraction2) = (locate(pair[0].pt), locate(pair[1].pt));
+            matches.push(Match {
+                link,
+                interval: Interval {
+                    length: pair[1].dist_along - pair[0].dist_along,
+                    width1: pair[0].width(),
+                    width2: pair[1].width(),
+                },
+                covered: (fraction1.min(fraction2), fraction1.max(fraction2)),
+                side,
+            });
+        }
+    }
+    matches
+}
+
+// The link closest to the middle of the piece of center line, if any are close enough and
+// pointing the same way. Also returns the closest segment of that link.
+fn best_link(
+    tree: &RTree<LinkSegment>,
+    piece: Line,
+    cfg: &ConflationConfig,
+) -> Option<(usize, Line)> {
+    if piece.start == piece.end {
+        return None;
+    }
+    let midpoint = (piece.start + piece.end) / 2.0;
+    let angle = angle_degrees(piece);
+
+    let mut best: Option<(usize, Line, f64)> = None;
+    for segment in tree.locate_within_distance(midpoint.into(), cfg.max_distance.powi(2)) {
+        let line = Line::new(segment.geom().from, segment.geom().to);
+        if line.start == line.end || angle_difference(angle, angle_degrees(line)) > cfg.max_angle {
+            continue;
+        }
+        let dist = segment.distance_2(&midpoint.into());
+        if !matches!(best, Some((_, _, best_dist)) if best_dist <= dist) {
+            best = Some((segment.data, line, dist));
+        }
+    }
+    best.map(|(link, line, _)| (link, line))
+}
+
+// Negative on the left of the line, positive on the right, in meters. Mercator's Y axis points
+// down.
+fn side_of_line(line: Line, pt: Coord) -> f64 {
+    let v = pt - line.start;
+    (line.dx() * v.y - line.dy() * v.x) / line.euclidean_length()
+}
+
+fn angle_degrees(line: Line) -> f64 {
+    line.dy().atan2(line.dx()).to_degrees()
+}
+
+// Between 0 and 90 degrees, ignoring which way each line points
+fn angle_difference(angle1: f64, angle2: f64) -> f64 {
+    let diff = (angle1 - angle2).rem_euclid(180.0);
+    diff.min(180.0 - diff)
+}
+
+// The total length covered by possibly overlapping ranges
+pub(crate) fn union_length(mut ranges: Vec<(f64, f64)>) -> f64 {
+    ranges.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let mut total = 0.0;
+    let mut current: Option<(f64, f64)> = None;
+    for (start, end) in ranges {
+        match current {
+            Some((current_start, current_end)) if start <= current_end => {
+                current = Some((current_start, current_end.max(end)));
+            }
+            _ => {
+                if let Some((current_start, current_end)) = current {
+                    total += current_end - current_start;
+                }
+                current = Some((start, end));
+            }
+        }
+    }
+    if let Some((current_start, current_end)) = current {
+        total += current_end - current_start;
+    }
+    total
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{assign_centerlines, Config};
+    use geo::Polygon;
+
+    // A 20x4 rectangle, measured along its middle. Samples are at 0, 5, 10 and 15.
+    pub(crate) fn measured_rectangle() -> Pavement {
+        let cfg = Config::default();
+        let polygon = Polygon::new(
+            LineString::from(vec![
+                (0.0, -2.0),
+                (20.0, -2.0),
+                (20.0, 2.0),
+                (0.0, 2.0),
+                (0.0, -2.0),
+            ]),
+            Vec::new(),
+        );
+        let mut pavements = vec![Pavement::new(polygon, &cfg)];
+        assign_centerlines(
+            &mut pavements,
+            &[LineString::from(vec![(0.0, 0.0), (20.0, 0.0)])],
+        );
+        pavements[0].calculate(&cfg);
+        pavements.pop().unwrap()
+    }
+
+    #[test]
+    fn widths_go_to_the_parallel_link() {
+        let pavements = vec![measured_rectangle()];
+        let links = vec![
+            // Parallel, offset to one side, and twice as long
+            LineString::from(vec![(0.0, 1.0), (40.0, 1.0)]),
+            // Crossing in the middle
+            LineString::from(vec![(10.0, -10.0), (10.0, 10.0)]),
+        ];
+        let results = conflate(&pavements, &links, &ConflationConfig::default());
+
+        assert!((results[0].coverage - 15.0 / 40.0).abs() < 1e-9);
+        let stats = results[0].stats.as_ref().unwrap();
+        assert_eq!(stats.matched_length, 15.0);
+        assert_eq!(stats.mean_width, 4.0);
+
+        assert_eq!(results[1].coverage, 0.0);
+        assert!(results[1].stats.is_none());
+    }
+
+    #[test]
+    fn union_of_ranges() {
+        assert!((union_length(vec![(0.5, 0.7), (0.0, 0.2), (0.1, 0.3)]) - 0.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,139 @@
+use serde::Deserialize;
+
+use crate::CenterWithWidth;
+
+/// The width in meters of each element of a road's cross-section, such as from LTN 1/20
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct LaneWidths {
+    pub traffic_lane: f64,
+    pub bus_lane: f64,
+    pub one_way_cycle_lane: f64,
+    pub two_way_cycle_track: f64,
+    pub buffer: f64,
+    // How many general traffic lanes every layout must keep
+    pub traffic_lanes: usize,
+}
+
+/// One way to arrange a road
+pub struct CrossSection {
+    // A name to use in output properties
+    pub key: &'static str,
+    pub required_width: f64,
+}
+
+/// How well a cross-section fits in one piece of center_with_width
+pub struct CrossSectionFit {
+    pub key: &'static str,
+    // How much narrower the road is than the cross-section needs, at its narrowest point. 0 if it
+    // fits.
+    pub shortfall: f64,
+}
+
+impl CrossSectionFit {
+    pub fn fits(&self) -> bool {
+        self.shortfall == 0.0
+    }
+}
+
+impl LaneWidths {
+    /// All of the layouts to try, assuming any cycle or bus lanes are on both sides of the road
+    pub fn cross_sections(&self) -> Vec<CrossSection> {
+        let traffic = (self.traffic_lanes as f64) * self.traffic_lane;
+        vec![
+            CrossSection {
+                key: "traffic_only",
+                required_width: traffic,
+            },
+            CrossSection {
+                key: "cycle_lanes",
+                required_width: traffic + 2.0 * self.one_way_cycle_lane,
+            },
+            CrossSection {
+                key: "protected_cycle_lanes",
+                required_width: traffic + 2.0 * (self.one_way_cycle_lane + self.buffer),
+            },
+            CrossSection {
+                key: "two_way_cycle_track",
+                required_width: traffic + self.two_way_cycle_track + self.buffer,
+            },
+            CrossSection {
+                key: "bus_lanes",
+                required_width: traffic + 2.0 * self.bus_lane,
+            },
+            CrossSection {
+                key: "bus_and_cycle_lanes",
+                required_width: traffic + 2.0 * (self.bus_lane + self.one_way_cycle_lane),
+            },
+        ]
+    }
+
+    /// For each piece of the center line, checks which cross-sections fit
+    pub fn analyze(&self, center_with_width: &[CenterWithWidth]) -> Vec<Vec<CrossSectionFit>> {
+        let cross_sections = self.cross_sections();
+        center_with_width
+            .iter()
+            .map(|piece| {
+                cross_sections
+                    .iter()
+                    .map(|cross_section| CrossSectionFit {
+                        key: cross_section.key,
+                        shortfall: (cross_section.required_width - piece.min_width).max(0.0),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WidthCategory;
+    use geo::LineString;
+
+    #[test]
+    fn shortfall_per_layout() {
+        let lane_widths = LaneWidths {
+            traffic_lane: 3.0,
+            bus_lane: 3.2,
+            one_way_cycle_lane: 2.0,
+            two_way_cycle_track: 3.0,
+            buffer: 0.5,
+            traffic_lanes: 2,
+        };
+        let piece = CenterWithWidth {
+            linestring: LineString::from(vec![(0.0, 0.0), (10.0, 0.0)]),
+            centerline: 0,
+            start_chainage: 0.0,
+            end_chainage: 10.0,
+            min_width: 10.0,
+            max_width: 12.0,
+            min_left: 5.0,
+            min_right: 5.0,
+            category: WidthCategory::Desirable,
+        };
+
+        let fits = lane_widths.analyze(&[piece]);
+        assert_eq!(fits.len(), 1);
+        let shortfalls: Vec<(&str, f64)> = fits[0]
+            .iter()
+            .map(|fit| (fit.key, (fit.shortfall * 100.0).round() / 100.0))
+            .collect();
+        assert_eq!(
+            shortfalls,
+            vec![
+                // 6m
+                ("traffic_only", 0.0),
+                // 10m exactly fits
+                ("cycle_lanes", 0.0),
+                ("protected_cycle_lanes", 1.0),
+                // 9.5m
+                ("two_way_cycle_track", 0.0),
+                ("bus_lanes", 2.4),
+                ("bus_and_cycle_lanes", 6.4),
+            ]
+        );
+        assert!(fits[0][1].fits());
+        assert!(!fits[0][2].fits());
+    }
+}
@@ -15,6 +15,7 @@ impl HashedPoint {
     }
 }
 
+/// Repeatedly joins lines that meet end-to-end, following the longest path through junctions
 pub fn join_linestrings(mut lines: Vec<LineString>) -> Vec<LineString> {
     loop {
         // Build a graph from the lines
@@ -122,3 +123,59 @@ fn join_path(lines: Vec<LineString>, path: Vec<EdgeIdx>) -> Vec<LineString> {
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coords(ls: &LineString) -> Vec<(f64, f64)> {
+        ls.coords().map(|c| (c.x, c.y)).collect()
+    }
+
+    #[test]
+    fn joins_a_chain_in_any_orientation() {
+        let lines = vec![
+            LineString::from(vec![(10.0, 0.0), (0.0, 0.0)]),
+            LineString::from(vec![(10.0, 0.0), (20.0, 0.0)]),
+            LineString::from(vec![(30.0, 5.0), (20.0, 0.0)]),
+        ];
+        let result = join_linestrings(lines);
+        assert_eq!(result.len(), 1);
+        let mut joined = coords(&result[0]);
+        // Either direction is fine
+        if joined[0] != (0.0, 0.0) {
+            joined.reverse();
+        }
+        assert_eq!(
+            joined,
+            vec![(0.0, 0.0), (10.0, 0.0), (20.0, 0.0), (30.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn keeps_the_longest_path_through_a_junction() {
+        let lines = vec![
+            LineString::from(vec![(0.0, 0.0), (10.0, 0.0)]),
+            LineString::from(vec![(10.0, 0.0), (20.0, 0.0)]),
+            LineString::from(vec![(10.0, 0.0), (10.0, 3.0)]),
+        ];
+        let result = join_linestrings(lines);
+        assert_eq!(result.len(), 2);
+        let mut joined = coords(&result[0]);
+        if joined[0] != (0.0, 0.0) {
+            joined.reverse();
+        }
+        assert_eq!(joined, vec![(0.0, 0.0), (10.0, 0.0), (20.0, 0.0)]);
+        assert_eq!(coords(&result[1]), vec![(10.0, 0.0), (10.0, 3.0)]);
+    }
+
+    #[test]
+    fn disconnected_lines_are_unchanged() {
+        let lines = vec![
+            LineString::from(vec![(0.0, 0.0), (10.0, 0.0)]),
+            LineString::from(vec![(0.0, 5.0), (10.0, 5.0)]),
+        ];
+        let result = join_linestrings(lines.clone());
+        assert_eq!(result, lines);
+    }
+}
@@ -1,61 +1,360 @@
-mod join_lines;
+mod boundary_index;
+mod centerlines;
+mod conflation;
+mod cross_sections;
+pub mod join_lines;
 mod mercator;
-mod split_line;
-mod step_along_line;
+mod merge;
+mod osm;
+mod pinch_points;
+mod repair;
+mod segments;
+pub mod split_line;
+mod stages;
+mod stats;
+pub mod step_along_line;
+pub mod synthetic;
 pub mod utils;
+mod width_bands;
+mod width_profile;
 
+use boundary_index::BoundaryIndex;
+pub use centerlines::assign_centerlines;
+pub use conflation::{conflate, ConflationConfig, LinkStats, LinkWidths};
+pub use cross_sections::{CrossSection, CrossSectionFit, LaneWidths};
 use geo::{
-    Area, Contains, Coord, EuclideanDistance, EuclideanLength, Line, LineInterpolatePoint,
-    LineIntersection, LineLocatePoint, LineString, Polygon,
+    Area, BooleanOps, BoundingRect, Contains, Coord, EuclideanDistance, EuclideanLength,
+    Intersects, Line, LineInterpolatePoint, LineIntersection, LineLocatePoint, LineString,
+    MultiLineString, Point, Polygon,
 };
+use geojson::Feature;
 pub use mercator::Mercator;
+pub use merge::{merge_pavements, MergeConfig};
+pub use osm::{suggest_osm_tags, TagSuggestion};
+pub use pinch_points::PinchPoint;
+pub use repair::RepairReport;
+pub use segments::{split_pavements, SplitConfig};
 use serde::Deserialize;
+pub use stages::Stage;
+pub use stats::PavementStats;
+pub use width_bands::{WidthBands, WidthCategory};
+pub use width_profile::{ProfilePoint, WidthProfile};
 
 pub struct Pavement {
-    // input
+    // The input after repair, before Config::remove_holes
+    input: Polygon,
+    // What was fixed about the input to make it valid
+    pub repairs: RepairReport,
+    // The input after Config::remove_holes
     pub polygon: Polygon,
+    // The IDs of the input features this came from, if the input format has them. Multipolygons
+    // produce one Pavement per polygon, all with the same ID. merge_pavements combines the IDs of
+    // every polygon merged.
+    pub source_ids: Vec<String>,
+    // If this is a piece from split_pavements, the index of the pavement it was cut from, in the
+    // list passed in. The CLI writes that list to output/unsplit_polygons.geojson.
+    pub parent: Option<usize>,
+    // the lines along the polygon's boundary, built once in new
+    boundary: BoundaryIndex,
+    // Known center lines, like OSM footways. If set, skeletonize clips these to the polygon
+    // instead of finding skeletons.
+    centerlines: Option<Vec<LineString>>,
 
     // should be center line
     pub skeletons: Vec<LineString>,
 
-    // regularly spaced lines that measure width
-    pub perp_lines: Vec<Line>,
+    // regularly spaced lines that measure width, one list per skeleton
+    pub samples: Vec<Vec<Sample>>,
 
-    // thickened center lines, along with their width at each end
-    pub thickened_lines: Vec<(Polygon, f64, f64)>,
+    // thickened center lines between each pair of samples
+    pub thickened_lines: Vec<ThickenedLine>,
 
-    // The center line and its min and max width, split up into when the width changes past some
-    // threshold
-    pub center_with_width: Vec<(LineString, f64, f64)>,
+    // The center line and its width, split up into when the width changes past some threshold
+    pub center_with_width: Vec<CenterWithWidth>,
+
+    // Local narrowings, over all skeletons
+    pub pinch_points: Vec<PinchPoint>,
+
+    // For each center_with_width, which road layouts fit. Empty unless cfg.lane_widths is set.
+    pub cross_sections: Vec<Vec<CrossSectionFit>>,
+
+    // None if there weren't enough samples
+    pub stats: Option<PavementStats>,
+
+    // The config that everything above was calculated with, or None before calculate
+    cfg: Option<Config>,
 }
 
-impl Pavement {
-    pub fn new(mut polygon: Polygon, cfg: &Config) -> Self {
-        // Remove small holes, representing bus stops in the example input
-        if let Some(limit) = cfg.remove_holes {
-            let (exterior, mut holes) = polygon.into_inner();
-            holes.retain(|hole| {
-                let p = Polygon::new(hole.clone(), Vec::new());
-                p.unsigned_area() > limit
-            });
-            polygon = Polygon::new(exterior, holes);
+/// One width measurement along a skeleton
+#[derive(Clone, Copy)]
+pub struct Sample {
+    // on the skeleton
+    pub pt: Coord,
+    // the angle of the skeleton at pt, in degrees
+    pub angle: f64,
+    // distance from the start of the skeleton to pt, in meters
+    pub dist_along: f64,
+    // the measured line across the polygon, oriented from the left side to the right
+    pub perp: Line,
+    // distance from pt to the boundary on each side, along perp. The skeleton may not be centered.
+    // For WidthMethod::MinimumChord, perp may be at an angle; see offsets.
+    pub left: f64,
+    pub right: f64,
+    // the perpendicular line didn't reach the boundary on at least one side, so that side is
+    // capped at the maximum projection distance
+    pub unbounded: bool,
+}
+
+impl Sample {
+    /// Returns None if both ends of the perpendicular line are on the same side of the skeleton
+    fn new(pt: Coord, angle: f64, dist_along: f64, perp: Line, unbounded: bool) -> Option<Self> {
+        let side1 = side_of_skeleton(pt, angle, perp.start);
+        let side2 = side_of_skeleton(pt, angle, perp.end);
+        if side1 * side2 > 0.0 {
+            return None;
         }
+        // Left is angle - 90, which is negative
+        let perp = if side1 > 0.0 {
+            Line::new(perp.end, perp.start)
+        } else {
+            perp
+        };
+        Some(Self {
+            pt,
+            angle,
+            dist_along,
+            perp,
+            left: pt.euclidean_distance(&perp.start),
+            right: pt.euclidean_distance(&perp.end),
+            unbounded,
+        })
+    }
+
+    pub fn width(&self) -> f64 {
+        self.left + self.right
+    }
+
+    /// The distance from each end of perp to the skeleton, measured perpendicular to the skeleton.
+    /// The same as left and right, unless perp is at an angle.
+    pub fn offsets(&self) -> (f64, f64) {
+        (
+            side_of_skeleton(self.pt, self.angle, self.perp.start).abs(),
+            side_of_skeleton(self.pt, self.angle, self.perp.end).abs(),
+        )
+    }
 
+    /// Adds properties to a GeoJSON feature of perp. This is `samples[centerline][idx]`.
+    pub fn set_properties(&self, f: &mut Feature, centerline: usize, idx: usize) {
+        f.set_property("unbounded", self.unbounded);
+        f.set_property("centerline", centerline);
+        f.set_property("sample", idx);
+        f.set_property("chainage", self.dist_along);
+    }
+}
+
+#[cfg(test)]
+impl Sample {
+    // A sample along a straight line heading east, centered in the polygon
+    pub(crate) fn heading_east(dist_along: f64, width: f64) -> Self {
         Self {
+            pt: Coord {
+                x: dist_along,
+                y: 0.0,
+            },
+            angle: 0.0,
+            dist_along,
+            perp: Line::new(
+                Coord {
+                    x: dist_along,
+                    y: -width / 2.0,
+                },
+                Coord {
+                    x: dist_along,
+                    y: width / 2.0,
+                },
+            ),
+            left: width / 2.0,
+            right: width / 2.0,
+            unbounded: false,
+        }
+    }
+}
+
+/// A piece of the center line where the width doesn't vary much
+pub struct CenterWithWidth {
+    pub linestring: LineString,
+    // Index into skeletons
+    pub centerline: usize,
+    // Distance from the start of the center line to each end of this piece, in meters
+    pub start_chainage: f64,
+    pub end_chainage: f64,
+    pub min_width: f64,
+    pub max_width: f64,
+    // The narrowest distance from the center line to the boundary on each side
+    pub min_left: f64,
+    pub min_right: f64,
+    pub category: WidthCategory,
+}
+
+impl CenterWithWidth {
+    /// Adds all results as properties of a GeoJSON feature, with the shortfall of each of `fits`
+    /// from `Pavement::cross_sections`, if there are any
+    pub fn set_properties(&self, f: &mut Feature, fits: &[CrossSectionFit]) {
+        f.set_property("centerline", self.centerline);
+        f.set_property("start_chainage", self.start_chainage);
+        f.set_property("end_chainage", self.end_chainage);
+        f.set_property("min_width", self.min_width);
+        f.set_property("max_width", self.max_width);
+        f.set_property("min_left_width", self.min_left);
+        f.set_property("min_right_width", self.min_right);
+        f.set_property("category", self.category.key());
+        if !fits.is_empty() {
+            for fit in fits {
+                f.set_property(format!("{}_shortfall", fit.key), fit.shortfall);
+            }
+            let keys: Vec<&str> = fits.iter().filter(|x| x.fits()).map(|x| x.key).collect();
+            f.set_property("fitting_cross_sections", keys);
+        }
+    }
+}
+
+/// The center line between two consecutive samples, thickened to the width on each side
+pub struct ThickenedLine {
+    pub polygon: Polygon,
+    // Index into skeletons and samples
+    pub centerline: usize,
+    // Index of the first sample within samples[centerline]. The second is the next one.
+    pub sample: usize,
+    // The width at each end
+    pub width1: f64,
+    pub width2: f64,
+    // Distance from the start of the center line to each end, in meters
+    pub start_chainage: f64,
+    pub end_chainage: f64,
+}
+
+impl ThickenedLine {
+    /// Adds all results as properties of a GeoJSON feature
+    pub fn set_properties(&self, f: &mut Feature) {
+        f.set_property("width1", self.width1);
+        f.set_property("width2", self.width2);
+        f.set_property("centerline", self.centerline);
+        f.set_property("sample", self.sample);
+        f.set_property("start_chainage", self.start_chainage);
+        f.set_property("end_chainage", self.end_chainage);
+    }
+}
+
+impl Pavement {
+    /// Repairs the input if it's invalid, recording what changed in `repairs`
+    pub fn new(input: Polygon, cfg: &Config) -> Self {
+        let (input, repairs) = repair::repair(&input);
+        let polygon = remove_holes(&input, cfg);
+        Self {
+            boundary: BoundaryIndex::new(&polygon),
+            input,
+            repairs,
             polygon,
+            centerlines: None,
+            source_ids: Vec::new(),
+            parent: None,
             skeletons: Vec::new(),
-            perp_lines: Vec::new(),
+            samples: Vec::new(),
             thickened_lines: Vec::new(),
             center_with_width: Vec::new(),
+            pinch_points: Vec::new(),
+            cross_sections: Vec::new(),
+            stats: None,
+            cfg: None,
+        }
+    }
+
+    /// Runs every stage that's out-of-date, returning the stages that ran. The first call runs
+    /// everything. Later calls compare `cfg` with the previous config and only rerun the stages
+    /// depending on a changed setting, so tuning the later settings doesn't redo the skeletons.
+    pub fn calculate(&mut self, cfg: &Config) -> Vec<Stage> {
+        let stale = Stage::stale(self.cfg.as_ref(), cfg);
+        for stage in &stale {
+            self.run_stage(*stage, cfg);
+        }
+        self.cfg = Some(cfg.clone());
+        stale
+    }
+
+    /// Adds the source IDs, repairs and stats as properties of a GeoJSON feature
+    pub fn set_properties(&self, f: &mut Feature) {
+        if !self.source_ids.is_empty() {
+            f.set_property("source_id", self.source_ids.join(";"));
+        }
+        if let Some(parent) = self.parent {
+            f.set_property("parent", parent);
+        }
+        self.repairs.set_properties(f);
+        if let Some(ref stats) = self.stats {
+            stats.set_properties(f);
         }
     }
 
-    pub fn calculate(&mut self, cfg: &Config) {
-        self.skeletonize(cfg);
-        self.make_perp_lines(cfg);
+    /// Forgets the config used last, so the next `calculate` runs everything. Call this after
+    /// modifying any results directly.
+    pub fn invalidate(&mut self) {
+        self.cfg = None;
+    }
+
+    /// Uses these lines (in Mercator) as the center lines, clipped to the polygon, instead of
+    /// finding skeletons. The skeleton settings in Config are then ignored.
+    pub fn set_centerlines(&mut self, lines: Vec<LineString>) {
+        self.centerlines = Some(lines);
+        self.invalidate();
+    }
+
+    fn run_stage(&mut self, stage: Stage, cfg: &Config) {
+        match stage {
+            Stage::RemoveHoles => {
+                self.polygon = remove_holes(&self.input, cfg);
+                self.boundary = BoundaryIndex::new(&self.polygon);
+            }
+            Stage::Skeletonize => self.skeletonize(cfg),
+            Stage::MakePerpLines => self.make_perp_lines(cfg),
+            Stage::SplitLines => self.split_lines(cfg),
+            Stage::PinchPoints => {
+                self.pinch_points.clear();
+                if let Some(prominence) = cfg.pinch_point_prominence {
+                    for samples in &self.samples {
+                        self.pinch_points.extend(pinch_points::find_pinch_points(
+                            samples,
+                            prominence,
+                            cfg.pinch_point_window,
+                        ));
+                    }
+                }
+            }
+            Stage::CrossSections => {
+                self.cross_sections = match cfg.lane_widths {
+                    Some(ref lane_widths) => lane_widths.analyze(&self.center_with_width),
+                    None => Vec::new(),
+                };
+            }
+            Stage::Stats => {
+                self.stats = PavementStats::new(self, cfg);
+            }
+        }
     }
 
-    fn skeletonize(&mut self, cfg: &Config) {
+    /// Finds the center lines of the polygon. This and the other expensive stages are public so
+    /// they can be benchmarked individually; `calculate` is otherwise the way to run them.
+    pub fn skeletonize(&mut self, cfg: &Config) {
+        self.skeletons.clear();
+        if let Some(ref lines) = self.centerlines {
+            let clipped = self
+                .polygon
+                .clip(&MultiLineString::new(lines.clone()), false);
+            self.skeletons = clipped
+                .into_iter()
+                .filter(|ls| ls.euclidean_length() > 0.0)
+                .collect();
+            return;
+        }
         let mut skeletons = Vec::new();
 
         // TODO We want the lines inside, but this seems to give the wrong answer for some inputs,
@@ -118,72 +417,133 @@ impl Pavement {
         }
     }
 
-    fn make_perp_lines(&mut self, cfg: &Config) {
+    /// Measures the width along each skeleton. Needs `skeletonize` first.
+    pub fn make_perp_lines(&mut self, cfg: &Config) {
+        self.samples.clear();
+        self.thickened_lines.clear();
         let Some(step_size_meters) = cfg.make_perps_step_size else {
             return;
         };
-        let project_away_meters = 100.0;
-
-        for skeleton in &self.skeletons {
-            let mut thickened_points = Vec::new();
-            for (pt, angle) in crate::step_along_line::step_along_line(skeleton, step_size_meters) {
-                let pt1 = project_away(pt, angle - 90.0, project_away_meters);
-                let pt2 = project_away(pt, angle + 90.0, project_away_meters);
-
-                let Some(perp) = clip_line_to_polygon(&self.polygon, pt, Line::new(pt1, pt2), cfg)
-                else {
+        if self.skeletons.is_empty() {
+            return;
+        }
+        let project_away_meters = cfg.max_perp_length.unwrap_or_else(|| {
+            // From any point inside the polygon, this reaches the boundary in every direction
+            let bbox = self.polygon.bounding_rect().unwrap();
+            bbox.width().hypot(bbox.height())
+        });
+
+        for (centerline, skeleton) in self.skeletons.iter().enumerate() {
+            let mut samples = Vec::new();
+            for step in crate::step_along_line::step_along_line(skeleton, step_size_meters) {
+                let (pt, angle, dist_along) = (step.pt, step.angle, step.dist_along);
+                let perp = match cfg.width_method {
+                    WidthMethod::Perpendicular => {
+                        let pt1 = project_away(pt, angle - 90.0, project_away_meters);
+                        let pt2 = project_away(pt, angle + 90.0, project_away_meters);
+                        clip_line_to_polygon(
+                            &self.polygon,
+                            &self.boundary,
+                            pt,
+                            Line::new(pt1, pt2),
+                            cfg.perp_midpoint_ratio,
+                        )
+                    }
+                    WidthMethod::MinimumChord => min_chord(
+                        &self.polygon,
+                        &self.boundary,
+                        pt,
+                        angle,
+                        project_away_meters,
+                        cfg.min_chord_angle_step,
+                    ),
+                };
+                let Some(sample) = perp.and_then(|(perp, unbounded)| {
+                    Sample::new(pt, angle, dist_along, perp, unbounded)
+                }) else {
                     continue;
                 };
-                let width = perp.euclidean_length();
                 // TODO Oh hey, happens to be a good heuristic to prune out weird stuff?!
-                if width == 0.0 {
+                if sample.width() == 0.0 {
                     // TODO remove this skeleton???
                     continue;
                 }
 
-                self.perp_lines.push(perp);
-
-                thickened_points.push((pt, angle, width));
+                samples.push(sample);
             }
-            if thickened_points.len() < 2 {
-                continue;
+            if samples.len() >= 2 {
+                // Make thickened polygons that may have different widths on each end and side. The
+                // ends of each perp are on the boundary, whatever angle it's at.
+                // TODO Make sure we have points at the very start and end. Ideally we do that with
+                // step_along_line
+                for (sample, pair) in samples.windows(2).enumerate() {
+                    let s1 = pair[0];
+                    let s2 = pair[1];
+
+                    self.thickened_lines.push(ThickenedLine {
+                        polygon: Polygon::new(
+                            LineString::new(vec![
+                                s1.perp.start,
+                                s1.perp.end,
+                                s2.perp.end,
+                                s2.perp.start,
+                                s1.perp.start,
+                            ]),
+                            Vec::new(),
+                        ),
+                        centerline,
+                        sample,
+                        width1: s1.width(),
+                        width2: s2.width(),
+                        start_chainage: s1.dist_along,
+                        end_chainage: s2.dist_along,
+                    });
+                }
             }
 
-            // Make thickened polygons that may have different widths on each end
-            // TODO Make sure we have points at the very start and end. Ideally we do that with
-            // step_along_line
-            for pair in thickened_points.windows(2) {
-                let (pt1, angle1, width1) = pair[0];
-                let (pt2, angle2, width2) = pair[1];
-
-                self.thickened_lines.push((
-                    Polygon::new(
-                        LineString::new(vec![
-                            project_away(pt1, angle1 - 90.0, width1 / 2.0),
-                            project_away(pt1, angle1 + 90.0, width1 / 2.0),
-                            project_away(pt2, angle2 + 90.0, width2 / 2.0),
-                            project_away(pt2, angle2 - 90.0, width2 / 2.0),
-                            project_away(pt1, angle1 - 90.0, width1 / 2.0),
-                        ]),
-                        Vec::new(),
-                    ),
-                    width1,
-                    width2,
+            self.samples.push(samples);
+        }
+    }
+
+    /// Splits each skeleton up by width. Needs `make_perp_lines` first.
+    pub fn split_lines(&mut self, cfg: &Config) {
+        self.center_with_width.clear();
+        for (centerline, (skeleton, samples)) in
+            self.skeletons.iter().zip(&self.samples).enumerate()
+        {
+            if samples.len() >= 2 {
+                self.center_with_width.extend(split_line::split(
+                    skeleton,
+                    centerline,
+                    samples,
+                    cfg.width_granularity,
+                    &cfg.width_bands,
                 ));
             }
-
-            self.center_with_width.extend(split_line::split(
-                skeleton,
-                thickened_points
-                    .into_iter()
-                    .map(|(pt, _, width)| (pt, width))
-                    .collect(),
-                cfg.width_granularity,
-            ));
         }
     }
 }
 
+// Removes small holes, representing bus stops in the example input
+fn remove_holes(polygon: &Polygon, cfg: &Config) -> Polygon {
+    let Some(limit) = cfg.remove_holes else {
+        return polygon.clone();
+    };
+    let mut holes = polygon.interiors().to_vec();
+    holes.retain(|hole| {
+        let p = Polygon::new(hole.clone(), Vec::new());
+        p.unsigned_area() > limit
+    });
+    Polygon::new(polygon.exterior().clone(), holes)
+}
+
+// Negative on the left of the skeleton, positive on the right. Mercator's Y axis points down.
+fn side_of_skeleton(pt: Coord, angle_degrees: f64, other: Coord) -> f64 {
+    let (sin, cos) = angle_degrees.to_radians().sin_cos();
+    let v = other - pt;
+    cos * v.y - sin * v.x
+}
+
 fn project_away(pt: Coord, angle_degrees: f64, distance: f64) -> Coord {
     let (sin, cos) = angle_degrees.to_radians().sin_cos();
     Coord {
@@ -192,39 +552,67 @@ fn project_away(pt: Coord, angle_degrees: f64, distance: f64) -> Coord {
     }
 }
 
+/// Finds the piece of `line` inside the polygon that contains `midpoint`, which must be somewhere
+/// along `line`. The result is oriented the same way as `line`. If the polygon continues past
+/// either end of `line`, the result is cut off there and also returns true.
 fn clip_line_to_polygon(
     polygon: &Polygon,
+    boundary: &BoundaryIndex,
     midpoint: Coord,
     line: Line,
-    cfg: &Config,
-) -> Option<Line> {
-    let mut hits = Vec::new();
-    for boundary in vec![polygon.exterior()]
-        .into_iter()
-        .chain(polygon.interiors())
-    {
-        for polygon_line in boundary.lines() {
-            if let Some(LineIntersection::SinglePoint { intersection, .. }) =
-                geo::algorithm::line_intersection::line_intersection(line, polygon_line)
-            {
-                hits.push(intersection);
+    perp_midpoint_ratio: Option<f64>,
+) -> Option<(Line, bool)> {
+    if !polygon.intersects(&Point::from(midpoint)) {
+        return None;
+    }
+
+    // The line might hit the polygon at many points, like near holes or concave corners. Only the
+    // closest hit on each side of the midpoint bounds the piece we want. Track them as a signed
+    // distance along the line from the midpoint.
+    let len = line.euclidean_length();
+    let signed_dist = |pt: Coord| {
+        let v = pt - midpoint;
+        (v.x * line.dx() + v.y * line.dy()) / len
+    };
+    let mut before = f64::NEG_INFINITY;
+    let mut after = f64::INFINITY;
+    let mut add_hit = |pt: Coord| {
+        let dist = signed_dist(pt);
+        if dist <= 0.0 && dist > before {
+            before = dist;
+        }
+        if dist >= 0.0 && dist < after {
+            after = dist;
+        }
+    };
+
+    for polygon_line in boundary.candidates(line) {
+        match geo::algorithm::line_intersection::line_intersection(line, polygon_line) {
+            Some(LineIntersection::SinglePoint { intersection, .. }) => {
+                add_hit(intersection);
             }
+            // The line runs along the boundary for a bit. Both ends count.
+            Some(LineIntersection::Collinear { intersection }) => {
+                add_hit(intersection.start);
+                add_hit(intersection.end);
+            }
+            None => {}
         }
     }
-
-    // The line might hit the polygon at more than 2 points. Find the two closest hits to the
-    // the midpoint (of the clipped line we should return)
-    hits.sort_by_key(|pt| (midpoint.euclidean_distance(pt) * 1000.0) as usize);
-    if hits.len() < 2 {
-        return None;
+    let unbounded = before.is_infinite() || after.is_infinite();
+    if before.is_infinite() {
+        before = signed_dist(line.start);
+    }
+    if after.is_infinite() {
+        after = signed_dist(line.end);
     }
 
     // Check if midpoint is really acting like a midpoint. There are false positives near sharp
     // corners, where the left and right projection are very different. This requires midpoint to
     // really be on the polygon's center line.
-    if let Some(threshold) = cfg.perp_midpoint_ratio {
-        let mut dist1 = hits[0].euclidean_distance(&midpoint);
-        let mut dist2 = hits[1].euclidean_distance(&midpoint);
+    if let Some(threshold) = perp_midpoint_ratio {
+        let mut dist1 = -before;
+        let mut dist2 = after;
         if dist1 > dist2 {
             std::mem::swap(&mut dist1, &mut dist2);
         }
@@ -233,10 +621,49 @@ fn clip_line_to_polygon(
         }
     }
 
-    Some(Line::new(hits[0], hits[1]))
+    let along = |dist: f64| Coord {
+        x: midpoint.x + dist * line.dx() / len,
+        y: midpoint.y + dist * line.dy() / len,
+    };
+    Some((Line::new(along(before), along(after)), unbounded))
 }
 
-#[derive(Deserialize)]
+/// Casts chords through `pt` at a range of angles, starting perpendicular to the center line, and
+/// returns the shortest one.
+fn min_chord(
+    polygon: &Polygon,
+    boundary: &BoundaryIndex,
+    pt: Coord,
+    angle: f64,
+    project_away_meters: f64,
+    angle_step: f64,
+) -> Option<(Line, bool)> {
+    // A chord at some angle is the same as one 180 degrees around, so only sweep half a circle
+    let angle_step = angle_step.max(0.1);
+    let num_chords = (180.0 / angle_step).ceil() as usize;
+    let mut best: Option<(Line, bool)> = None;
+    for i in 0..num_chords {
+        let chord_angle = angle + 90.0 + (i as f64) * angle_step;
+        let pt1 = project_away(pt, chord_angle, project_away_meters);
+        let pt2 = project_away(pt, chord_angle + 180.0, project_away_meters);
+        let Some((chord, unbounded)) =
+            clip_line_to_polygon(polygon, boundary, pt, Line::new(pt1, pt2), None)
+        else {
+            continue;
+        };
+
+        let shorter = match best {
+            Some((best, _)) => chord.euclidean_length() < best.euclidean_length(),
+            None => true,
+        };
+        if shorter {
+            best = Some((chord, unbounded));
+        }
+    }
+    best
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
 pub struct Config {
     // Remove smaller than this unsigned area in m^2
     pub remove_holes: Option<f64>,
@@ -247,11 +674,32 @@ pub struct Config {
     // When the ratio of a line to the longest line is less than this threshold, remove it
     pub remove_short_skeletons: Option<f64>,
 
+    // None, or a step that isn't positive, makes no samples
     pub make_perps_step_size: Option<f64>,
+    // How far to project perpendicular lines on each side, in meters. If None, use the polygon's
+    // bounding box diagonal, so lines always reach the boundary.
+    pub max_perp_length: Option<f64>,
+    pub width_method: WidthMethod,
+    // Only used for WidthMethod::Perpendicular
     pub perp_midpoint_ratio: Option<f64>,
+    // Only used for WidthMethod::MinimumChord, in degrees
+    pub min_chord_angle_step: f64,
 
     // For producing center_with_width, split the line when width differs by more than this amount
     pub width_granularity: f64,
+    // Also split the line when the width crosses into a different band
+    pub width_bands: WidthBands,
+
+    // A pinch point must be this much narrower (in meters) than the median width around it
+    pub pinch_point_prominence: Option<f64>,
+    // The length of center line (in meters) around each pinch point to compare against
+    pub pinch_point_window: f64,
+
+    // For stats, sum up the length of center line narrower than each of these widths
+    pub stats_thresholds: Vec<f64>,
+
+    // For roads, check which cross-sections fit
+    pub lane_widths: Option<LaneWidths>,
 }
 
 impl Config {
@@ -265,9 +713,272 @@ impl Config {
             remove_short_skeletons: Some(0.1),
 
             make_perps_step_size: Some(5.0),
+            max_perp_length: None,
+            width_method: WidthMethod::Perpendicular,
             perp_midpoint_ratio: Some(0.5),
+            min_chord_angle_step: 5.0,
 
             width_granularity: 0.5,
+            width_bands: WidthBands::new(1.5, 2.0).unwrap(),
+
+            pinch_point_prominence: Some(0.5),
+            pinch_point_window: 20.0,
+
+            stats_thresholds: vec![1.5, 2.0],
+
+            lane_widths: None,
+        }
+    }
+}
+
+/// How to measure the width at each point along the center line
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+pub enum WidthMethod {
+    /// Project a single line perpendicular to the center line
+    Perpendicular,
+    /// Cast chords at a range of angles through the point and keep the shortest
+    MinimumChord,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 20x4 strip
+    fn strip() -> Polygon {
+        Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (20.0, 0.0),
+                (20.0, 4.0),
+                (0.0, 4.0),
+                (0.0, 0.0),
+            ]),
+            Vec::new(),
+        )
+    }
+
+    // A 10x10 square with a 2x2 hole on the right side
+    fn square_with_hole() -> Polygon {
+        Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 10.0),
+                (0.0, 10.0),
+                (0.0, 0.0),
+            ]),
+            vec![LineString::from(vec![
+                (6.0, 4.0),
+                (8.0, 4.0),
+                (8.0, 6.0),
+                (6.0, 6.0),
+                (6.0, 4.0),
+            ])],
+        )
+    }
+
+    fn horizontal_line(y: f64, half_length: f64) -> Line {
+        Line::new(
+            Coord {
+                x: 5.0 - half_length,
+                y,
+            },
+            Coord {
+                x: 5.0 + half_length,
+                y,
+            },
+        )
+    }
+
+    #[test]
+    fn clip_stops_at_the_nearest_hit_on_each_side() {
+        let polygon = square_with_hole();
+        let (line, unbounded) = clip_line_to_polygon(
+            &polygon,
+            &BoundaryIndex::new(&polygon),
+            Coord { x: 2.0, y: 5.0 },
+            horizontal_line(5.0, 100.0),
+            None,
+        )
+        .unwrap();
+        // Not the two closest hits, which are both left of the hole
+        assert_eq!(
+            line,
+            Line::new(Coord { x: 0.0, y: 5.0 }, Coord { x: 6.0, y: 5.0 })
+        );
+        assert!(!unbounded);
+    }
+
+    #[test]
+    fn clip_handles_collinear_boundaries() {
+        let polygon = square_with_hole();
+        // Running along the top edge of the hole
+        let (line, _) = clip_line_to_polygon(
+            &polygon,
+            &BoundaryIndex::new(&polygon),
+            Coord { x: 3.0, y: 4.0 },
+            horizontal_line(4.0, 100.0),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            line,
+            Line::new(Coord { x: 0.0, y: 4.0 }, Coord { x: 6.0, y: 4.0 })
+        );
+    }
+
+    #[test]
+    fn clip_outside_polygon() {
+        let polygon = square_with_hole();
+        assert!(clip_line_to_polygon(
+            &polygon,
+            &BoundaryIndex::new(&polygon),
+            Coord { x: 7.0, y: 5.0 },
+            horizontal_line(5.0, 100.0),
+            None
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn clip_marks_unbounded_lines() {
+        let polygon = square_with_hole();
+        let (line, unbounded) = clip_line_to_polygon(
+            &polygon,
+            &BoundaryIndex::new(&polygon),
+            Coord { x: 4.0, y: 2.0 },
+            horizontal_line(2.0, 3.0),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            line,
+            Line::new(Coord { x: 2.0, y: 2.0 }, Coord { x: 8.0, y: 2.0 })
+        );
+        assert!(unbounded);
+    }
+
+    #[test]
+    fn clip_checks_midpoint_ratio() {
+        let polygon = square_with_hole();
+        // 2m on the left, 4m on the right
+        let midpoint = Coord { x: 2.0, y: 5.0 };
+        let line = horizontal_line(5.0, 100.0);
+        assert!(clip_line_to_polygon(
+            &polygon,
+            &BoundaryIndex::new(&polygon),
+            midpoint,
+            line,
+            Some(0.5)
+        )
+        .is_some());
+        assert!(clip_line_to_polygon(
+            &polygon,
+            &BoundaryIndex::new(&polygon),
+            midpoint,
+            line,
+            Some(0.6)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn min_chord_finds_the_narrowest_direction() {
+        let polygon = strip();
+        // If the skeleton is at an angle, the perpendicular line is too long
+        let pt = Coord { x: 10.0, y: 2.0 };
+        let angle: f64 = 30.0;
+        let perp = Line::new(
+            project_away(pt, angle - 90.0, 100.0),
+            project_away(pt, angle + 90.0, 100.0),
+        );
+        let (perp, _) =
+            clip_line_to_polygon(&polygon, &BoundaryIndex::new(&polygon), pt, perp, None).unwrap();
+        assert!((perp.euclidean_length() - 4.0 / angle.to_radians().cos()).abs() < 1e-6);
+
+        let (chord, _) = min_chord(
+            &polygon,
+            &BoundaryIndex::new(&polygon),
+            pt,
+            angle,
+            100.0,
+            5.0,
+        )
+        .unwrap();
+        assert!((chord.euclidean_length() - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn minimum_chord_pieces_end_on_the_boundary() {
+        let mut cfg = Config::default();
+        cfg.width_method = WidthMethod::MinimumChord;
+        cfg.perp_midpoint_ratio = None;
+        // Measured along a center line that isn't parallel to the strip
+        let polygon = strip();
+        let mut pavement = Pavement::new(polygon.clone(), &cfg);
+        pavement.set_centerlines(vec![LineString::from(vec![(0.0, 1.0), (20.0, 3.0)])]);
+        pavement.calculate(&cfg);
+
+        assert!(!pavement.thickened_lines.is_empty());
+        for thickened in &pavement.thickened_lines {
+            for pt in thickened.polygon.exterior().points() {
+                assert!(polygon.exterior().euclidean_distance(&pt) < 1e-6);
+            }
         }
     }
+
+    #[test]
+    fn offsets_are_perpendicular_to_the_skeleton() {
+        // A vertical chord through a skeleton heading 30 degrees south of east
+        let pt = Coord { x: 0.0, y: 0.0 };
+        let perp = Line::new(Coord { x: 0.0, y: -2.0 }, Coord { x: 0.0, y: 2.0 });
+        let sample = Sample::new(pt, 30.0, 0.0, perp, false).unwrap();
+        assert_eq!((sample.left, sample.right), (2.0, 2.0));
+        let (left, right) = sample.offsets();
+        assert!((left - 2.0 * 30_f64.to_radians().cos()).abs() < 1e-9);
+        assert!((right - 2.0 * 30_f64.to_radians().cos()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sample_orients_left_to_right() {
+        // Heading east. Mercator's Y axis points down, so the left side is north, with smaller Y.
+        let pt = Coord { x: 2.0, y: 5.0 };
+        let perp = Line::new(Coord { x: 2.0, y: 8.0 }, Coord { x: 2.0, y: 4.0 });
+        let sample = Sample::new(pt, 0.0, 0.0, perp, false).unwrap();
+        assert_eq!(sample.perp.start, Coord { x: 2.0, y: 4.0 });
+        assert_eq!(sample.left, 1.0);
+        assert_eq!(sample.right, 3.0);
+        assert_eq!(sample.width(), 4.0);
+
+        // Both ends on the same side
+        let perp = Line::new(Coord { x: 2.0, y: 6.0 }, Coord { x: 2.0, y: 8.0 });
+        assert!(Sample::new(pt, 0.0, 0.0, perp, false).is_none());
+    }
+
+    #[test]
+    fn calculate_reruns_remove_holes_from_the_input() {
+        let mut cfg = Config::default();
+        let mut pavement = Pavement::new(square_with_hole(), &cfg);
+        assert!(pavement.polygon.interiors().is_empty());
+
+        cfg.remove_holes = None;
+        assert_eq!(pavement.calculate(&cfg), Stage::ALL.to_vec());
+        assert_eq!(pavement.polygon.interiors().len(), 1);
+
+        cfg.stats_thresholds = vec![1.0];
+        assert_eq!(pavement.calculate(&cfg), vec![Stage::Stats]);
+        assert!(pavement.calculate(&cfg).is_empty());
+    }
+
+    #[test]
+    fn zero_step_size_makes_no_samples() {
+        let mut cfg = Config::default();
+        cfg.make_perps_step_size = Some(0.0);
+        let mut pavement = Pavement::new(square_with_hole(), &cfg);
+        pavement.set_centerlines(vec![LineString::from(vec![(0.0, 5.0), (10.0, 5.0)])]);
+        pavement.calculate(&cfg);
+        assert!(pavement.samples.iter().all(|samples| samples.is_empty()));
+        assert!(pavement.thickened_lines.is_empty());
+    }
 }
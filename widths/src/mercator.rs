@@ -74,3 +74,48 @@ impl Mercator {
 fn trim_lon_lat(x: f64) -> f64 {
     (x * 10e6).round() / 10e6
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mercator() -> Mercator {
+        Mercator::from(LineString::from(vec![(-0.102, 51.489), (-0.099, 51.490)])).unwrap()
+    }
+
+    #[test]
+    fn top_left_is_origin() {
+        let mercator = mercator();
+        let pt = mercator.pt_to_mercator(Coord {
+            x: -0.102,
+            y: 51.490,
+        });
+        assert!(pt.x.abs() < 1e-9);
+        assert!(pt.y.abs() < 1e-9);
+
+        let pt = mercator.pt_to_mercator(Coord {
+            x: -0.099,
+            y: 51.489,
+        });
+        assert!((pt.x - mercator.width).abs() < 1e-9);
+        assert!((pt.y - mercator.height).abs() < 1e-9);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mercator = mercator();
+        for (x, y) in [(-0.102, 51.489), (-0.1005, 51.4897), (-0.099, 51.490)] {
+            let wgs84 = Coord { x, y };
+            let result = mercator.pt_to_wgs84(mercator.pt_to_mercator(wgs84));
+            assert!((result.x - x).abs() < 1e-7);
+            assert!((result.y - y).abs() < 1e-7);
+        }
+    }
+
+    #[test]
+    fn units_are_meters() {
+        // 0.001 degrees of latitude is about 111m everywhere
+        let mercator = mercator();
+        assert!((mercator.height - 111.2).abs() < 0.5);
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use geo::{
+    BooleanOps, BoundingRect, Closest, ClosestPoint, EuclideanDistance, Intersects, LineString,
+    MultiPolygon, Point, Polygon,
+};
+use petgraph::unionfind::UnionFind;
+use rstar::{primitives::GeomWithData, primitives::Rectangle, RTree, AABB};
+
+use crate::{Config, Pavement};
+
+/// How to combine pavements in `merge_pavements`
+#[derive(Clone)]
+pub struct MergeConfig {
+    // Pavements closer than this many meters count as touching. Their vertices within this
+    // distance of each other are snapped together to close the gap.
+    pub max_gap: f64,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self { max_gap: 0.1 }
+    }
+}
+
+/// Dissolves touching or nearly touching pavements into one, so a footway split into fragments
+/// gets one continuous center line. If `groups` is given, with one value per pavement, only
+/// pavements with the same value are merged, and pavements with None aren't merged at all. Each
+/// result has the source IDs and center lines of every pavement merged into it, in order of the
+/// first one. Run this before `calculate`. `cfg` is used to make the new pavements. Everything
+/// must be in Mercator. Panics if `groups` has the wrong length.
+pub fn merge_pavements(
+    pavements: Vec<Pavement>,
+    groups: Option<&[Option<String>]>,
+    merge_cfg: &MergeConfig,
+    cfg: &Config,
+) -> Vec<Pavement> {
+    if let Some(groups) = groups {
+        assert_eq!(
+            groups.len(),
+            pavements.len(),
+            "merge_pavements needs one group per pavement"
+        );
+    }
+    let tree = RTree::bulk_load(
+        pavements
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, pavement)| {
+                let bbox = pavement.input.bounding_rect()?;
+                Some(GeomWithData::new(
+                    Rectangle::<[f64; 2]>::from_corners(bbox.min().into(), bbox.max().into()),
+                    idx,
+                ))
+            })
+            .collect(),
+    );
+
+    let mut components = UnionFind::new(pavements.len());
+    for (idx, pavement) in pavements.iter().enumerate() {
+        let Some(bbox) = pavement.input.bounding_rect() else {
+            continue;
+        };
+        let envelope = AABB::from_corners(
+            [
+                bbox.min().x - merge_cfg.max_gap,
+                bbox.min().y - merge_cfg.max_gap,
+            ],
+            [
+                bbox.max().x + merge_cfg.max_gap,
+                bbox.max().y + merge_cfg.max_gap,
+            ],
+        );
+        for obj in tree.locate_in_envelope_intersecting(&envelope) {
+            let other = obj.data;
+            // Pavements without a group aren't merged with anything
+            if other <= idx
+                || groups
+                    .is_some_and(|groups| groups[idx].is_none() || groups[idx] != groups[other])
+            {
+                continue;
+            }
+            if pavement.input.euclidean_distance(&pavements[other].input) <= merge_cfg.max_gap {
+                components.union(idx, other);
+            }
+        }
+    }
+
+    // Keep the pavements in order of the first one in each component
+    let mut component_per_label = HashMap::new();
+    let mut members: Vec<Vec<Pavement>> = Vec::new();
+    for (pavement, label) in pavements.into_iter().zip(components.into_labeling()) {
+        let idx = *component_per_label.entry(label).or_insert_with(|| {
+            members.push(Vec::new());
+            members.len() - 1
+        });
+        members[idx].push(pavement);
+    }
+
+    let mut results = Vec::new();
+    for mut group in members {
+        if group.len() == 1 {
+            results.push(group.pop().unwrap());
+            continue;
+        }
+
+        let mut merged = MultiPolygon::new(Vec::new());
+        let mut snapped = Vec::new();
+        for pavement in &group {
+            // Snap both ways, so a gap closes whichever side has the vertices near the other
+            let polygon = snap(&pavement.input, &merged, merge_cfg.max_gap);
+            let incoming = MultiPolygon::new(vec![polygon.clone()]);
+            merged = MultiPolygon::new(
+                merged
+                    .iter()
+                    .map(|p| snap(p, &incoming, merge_cfg.max_gap))
+                    .collect(),
+            );
+            merged = merged.union(&incoming);
+            snapped.push(polygon);
+        }
+
+        // If snapping didn't close every gap, each piece only gets the pavements it contains
+        for polygon in merged {
+            let mut source_ids = Vec::new();
+            let mut centerlines: Option<Vec<LineString>> = None;
+            for (pavement, member) in group.iter().zip(&snapped) {
+                if !polygon.intersects(member) {
+                    continue;
+                }
+                source_ids.extend(pavement.source_ids.clone());
+                if let Some(ref lines) = pavement.centerlines {
+                    centerlines
+                        .get_or_insert_with(Vec::new)
+                        .extend(lines.clone());
+                }
+            }
+            let mut result = Pavement::new(polygon, cfg);
+            result.source_ids = source_ids;
+            result.centerlines = centerlines;
+            results.push(result);
+        }
+    }
+    results
+}
+
+// Moves every vertex within max_gap of the other polygons onto their closest boundary point
+fn snap(polygon: &Polygon, others: &MultiPolygon, max_gap: f64) -> Polygon {
+    let rings: Vec<&LineString> = others
+        .iter()
+        .flat_map(|p| std::iter::once(p.exterior()).chain(p.interiors()))
+        .collect();
+    let snap_ring = |ring: &LineString| {
+        LineString::new(
+            ring.coords()
+                .map(|coord| {
+                    let pt = Point::from(*coord);
+                    let mut best = (*coord, max_gap);
+                    for other in &rings {
+                        if let Closest::SinglePoint(snapped) | Closest::Intersection(snapped) =
+                            other.closest_point(&pt)
+                        {
+                            let dist = pt.euclidean_distance(&snapped);
+                            if dist <= best.1 {
+                                best = (snapped.into(), dist);
+                            }
+                        }
+                    }
+                    best.0
+                })
+                .collect(),
+        )
+    };
+    Polygon::new(
+        snap_ring(polygon.exterior()),
+        polygon.interiors().iter().map(snap_ring).collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Area;
+
+    fn rectangle(x1: f64, x2: f64, id: &str) -> Pavement {
+        let polygon = Polygon::new(
+            LineString::from(vec![(x1, 0.0), (x2, 0.0), (x2, 4.0), (x1, 4.0), (x1, 0.0)]),
+            Vec::new(),
+        );
+        let mut pavement = Pavement::new(polygon, &Config::default());
+        pavement.source_ids = vec![id.to_string()];
+        pavement
+    }
+
+    fn pavements() -> Vec<Pavement> {
+        vec![
+            rectangle(0.0, 10.0, "a"),
+            // Far away
+            rectangle(50.0, 60.0, "b"),
+            // Touching a
+            rectangle(10.0, 20.0, "c"),
+            // A small gap from c
+            rectangle(20.05, 30.0, "d"),
+        ]
+    }
+
+    fn summarize(pavements: Vec<Pavement>) -> Vec<(Vec<String>, f64)> {
+        pavements
+            .into_iter()
+            .map(|p| (p.source_ids, p.polygon.unsigned_area().round()))
+            .collect()
+    }
+
+    #[test]
+    fn merge_touching() {
+        let cfg = Config::default();
+        let merged = merge_pavements(pavements(), None, &MergeConfig::default(), &cfg);
+        assert_eq!(
+            summarize(merged),
+            vec![
+                (vec!["a".into(), "c".into(), "d".into()], 120.0),
+                (vec!["b".into()], 40.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_by_group() {
+        let cfg = Config::default();
+        let groups = vec![
+            Some("footway".to_string()),
+            Some("footway".to_string()),
+            Some("footway".to_string()),
+            None,
+        ];
+        let merged = merge_pavements(pavements(), Some(&groups), &MergeConfig::default(), &cfg);
+        assert_eq!(
+            summarize(merged),
+            vec![
+                (vec!["a".into(), "c".into()], 80.0),
+                (vec!["b".into()], 40.0),
+                (vec!["d".into()], 40.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn pavements_without_a_group_stay_apart() {
+        let cfg = Config::default();
+        let groups = vec![None; 4];
+        let merged = merge_pavements(pavements(), Some(&groups), &MergeConfig::default(), &cfg);
+        assert_eq!(merged.len(), 4);
+    }
+
+    #[test]
+    fn close_the_gap_from_either_side() {
+        let cfg = Config::default();
+        // A narrow piece a small gap north of a wide one. None of the wide piece's vertices are
+        // near the narrow one, so only snapping the narrow piece closes the gap.
+        let narrow = Polygon::new(
+            LineString::from(vec![
+                (2.0, 4.05),
+                (8.0, 4.05),
+                (8.0, 8.0),
+                (2.0, 8.0),
+                (2.0, 4.05),
+            ]),
+            Vec::new(),
+        );
+        let mut narrow = Pavement::new(narrow, &cfg);
+        narrow.source_ids = vec!["narrow".to_string()];
+        let pavements = vec![narrow, rectangle(0.0, 10.0, "wide")];
+
+        let merged = merge_pavements(pavements, None, &MergeConfig::default(), &cfg);
+        assert_eq!(
+            summarize(merged),
+            vec![(vec!["narrow".into(), "wide".into()], 64.0)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "one group per pavement")]
+    fn groups_must_match_pavements() {
+        let groups = vec![Some("footway".to_string())];
+        merge_pavements(
+            pavements(),
+            Some(&groups),
+            &MergeConfig::default(),
+            &Config::default(),
+        );
+    }
+}
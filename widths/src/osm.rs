@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use geo::LineString;
+use serde::Serialize;
+
+use crate::conflation::{match_intervals, union_length, LinkStats, Side};
+use crate::{ConflationConfig, Pavement};
+
+/// A proposed tag for one OSM way, for a mapper to review
+#[derive(Serialize)]
+pub struct TagSuggestion {
+    // Index into the ways passed to suggest_osm_tags
+    pub way: usize,
+    pub key: &'static str,
+    // The median width in meters, rounded to 10cm
+    pub value: String,
+    // From 0 to 1. Lower when less of the way was measured, or when the width varies more along
+    // it.
+    pub confidence: f64,
+}
+
+/// Suggests `width` for ways running through a measured area, like a footway through its
+/// `area:highway` polygon, and `sidewalk:left:width` or `sidewalk:right:width` for ways with a
+/// measured area alongside, like a road with separately mapped sidewalks. Ways are matched like
+/// `conflate`. Everything must be in Mercator.
+pub fn suggest_osm_tags(
+    pavements: &[Pavement],
+    ways: &[LineString],
+    cfg: &ConflationConfig,
+) -> Vec<TagSuggestion> {
+    let mut groups: BTreeMap<(usize, &'static str), (Vec<_>, Vec<_>)> = BTreeMap::new();
+    for m in match_intervals(pavements, ways, cfg) {
+        let group = groups.entry((m.link, key(m.side))).or_default();
+        group.0.push(m.interval);
+        group.1.push(m.covered);
+    }
+
+    let mut suggestions = Vec::new();
+    for ((way, key), (mut intervals, covered)) in groups {
+        let Some(stats) = LinkStats::new(&mut intervals) else {
+            continue;
+        };
+        let Some((_, median)) = stats.percentiles.iter().find(|(pct, _)| *pct == 50) else {
+            continue;
+        };
+        let variation = (stats.std_dev_width / stats.mean_width).min(1.0);
+        suggestions.push(TagSuggestion {
+            way,
+            key,
+            value: format!("{}", (median * 10.0).round() / 10.0),
+            confidence: union_length(covered) * (1.0 - variation),
+        });
+    }
+    suggestions
+}
+
+fn key(side: Side) -> &'static str {
+    match side {
+        Side::Along => "width",
+        Side::Left => "sidewalk:left:width",
+        Side::Right => "sidewalk:right:width",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conflation::tests::measured_rectangle;
+
+    fn summarize(suggestions: Vec<TagSuggestion>) -> Vec<(usize, &'static str, String, f64)> {
+        suggestions
+            .into_iter()
+            .map(|x| (x.way, x.key, x.value, x.confidence))
+            .collect()
+    }
+
+    #[test]
+    fn width_and_sidewalks() {
+        let pavements = vec![measured_rectangle()];
+        let cfg = ConflationConfig::default();
+
+        // A footway through the area, slightly off-center, covering exactly the measured part
+        let footway = LineString::from(vec![(0.0, 1.0), (15.0, 1.0)]);
+        assert_eq!(
+            summarize(suggest_osm_tags(&pavements, &[footway], &cfg)),
+            vec![(0, "width", "4".to_string(), 1.0)]
+        );
+
+        // Eastbound roads south and north of the area. Mercator's Y axis points down, so the area
+        // is left of the southern road and right of the northern one.
+        let south_road = LineString::from(vec![(0.0, 6.0), (15.0, 6.0)]);
+        let north_road = LineString::from(vec![(0.0, -6.0), (15.0, -6.0)]);
+        assert_eq!(
+            summarize(suggest_osm_tags(&pavements, &[south_road], &cfg)),
+            vec![(0, "sidewalk:left:width", "4".to_string(), 1.0)]
+        );
+        assert_eq!(
+            summarize(suggest_osm_tags(&pavements, &[north_road], &cfg)),
+            vec![(0, "sidewalk:right:width", "4".to_string(), 1.0)]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use geo::{Coord, Line};
+use geojson::{Feature, JsonObject, JsonValue};
+
+use crate::{Mercator, Sample};
+
+/// A place where the pavement is much narrower than the stretch around it
+pub struct PinchPoint {
+    pub pt: Coord,
+    pub width: f64,
+    // The median width of samples within the window around this point
+    pub typical_width: f64,
+    // The measured line across the polygon at this point
+    pub chord: Line,
+}
+
+impl PinchPoint {
+    /// Adds the widths and the chord in WGS84 as properties of a GeoJSON feature
+    pub fn set_properties(&self, f: &mut Feature, mercator: &Mercator) {
+        f.set_property("width", self.width);
+        f.set_property("typical_width", self.typical_width);
+        let chord = geojson::Geometry::from(&mercator.to_wgs84(&self.chord));
+        f.set_property("chord", JsonValue::Object(JsonObject::from(&chord)));
+    }
+}
+
+/// Finds local minima of width along one skeleton that are at least `prominence` narrower than the
+/// median width within `window` meters (centered on the point). Pinch points within half a window
+/// of a narrower one are dropped.
+pub fn find_pinch_points(samples: &[Sample], prominence: f64, window: f64) -> Vec<PinchPoint> {
+    let mut candidates = Vec::new();
+    for (idx, sample) in samples.iter().enumerate() {
+        let width = sample.width();
+        // Only count the first sample of a flat stretch
+        let before = idx == 0 || width < samples[idx - 1].width();
+        let after = idx == samples.len() - 1 || width <= samples[idx + 1].width();
+        if !before || !after {
+            continue;
+        }
+
+        let mut nearby: Vec<f64> = samples
+            .iter()
+            .filter(|s| (s.dist_along - sample.dist_along).abs() <= window / 2.0)
+            .map(|s| s.width())
+            .collect();
+        nearby.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let typical_width = (nearby[(nearby.len() - 1) / 2] + nearby[nearby.len() / 2]) / 2.0;
+
+        if typical_width - width >= prominence {
+            candidates.push((sample, typical_width));
+        }
+    }
+
+    // Narrowest first, skipping anything too close to a narrower pinch point
+    candidates.sort_by(|a, b| a.0.width().partial_cmp(&b.0.width()).unwrap());
+    let mut kept: Vec<(&Sample, f64)> = Vec::new();
+    for (sample, typical_width) in candidates {
+        if kept
+            .iter()
+            .all(|(other, _)| (other.dist_along - sample.dist_along).abs() > window / 2.0)
+        {
+            kept.push((sample, typical_width));
+        }
+    }
+    kept.sort_by(|a, b| a.0.dist_along.partial_cmp(&b.0.dist_along).unwrap());
+
+    kept.into_iter()
+        .map(|(sample, typical_width)| PinchPoint {
+            pt: sample.pt,
+            width: sample.width(),
+            typical_width,
+            chord: sample.perp,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One sample per meter, starting at 0
+    fn samples(widths: &[f64]) -> Vec<Sample> {
+        widths
+            .iter()
+            .enumerate()
+            .map(|(idx, width)| Sample::heading_east(idx as f64, *width))
+            .collect()
+    }
+
+    fn summarize(pinch_points: Vec<PinchPoint>) -> Vec<(f64, f64, f64)> {
+        pinch_points
+            .into_iter()
+            .map(|p| (p.pt.x, p.width, p.typical_width))
+            .collect()
+    }
+
+    #[test]
+    fn first_sample_of_a_flat_stretch() {
+        let samples = samples(&[4.0, 4.0, 2.0, 2.0, 2.0, 4.0, 4.0]);
+        assert_eq!(
+            summarize(find_pinch_points(&samples, 1.0, 10.0)),
+            vec![(2.0, 2.0, 4.0)]
+        );
+    }
+
+    #[test]
+    fn median_within_the_window() {
+        let samples = samples(&[1.0, 1.0, 1.0, 4.0, 4.0, 3.0, 4.0, 4.0, 1.0, 1.0, 1.0]);
+        // Only 3 to 7 are nearby
+        assert_eq!(
+            summarize(find_pinch_points(&samples, 1.0, 4.0)),
+            vec![(5.0, 3.0, 4.0)]
+        );
+        // Most of the line is narrower than 3
+        assert!(find_pinch_points(&samples, 1.0, 20.0).is_empty());
+        // Not prominent enough
+        assert!(find_pinch_points(&samples, 1.5, 4.0).is_empty());
+    }
+
+    #[test]
+    fn keep_the_narrowest_within_half_a_window() {
+        let mut widths = vec![5.0; 21];
+        widths[5] = 2.0;
+        // Within 5m of the narrower one
+        widths[8] = 3.0;
+        // Far enough away
+        widths[16] = 3.0;
+        let samples = samples(&widths);
+        assert_eq!(
+            summarize(find_pinch_points(&samples, 1.0, 10.0)),
+            vec![(5.0, 2.0, 5.0), (16.0, 3.0, 5.0)]
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+
+use geo::{Area, Coord, EuclideanDistance, Line, LineIntersection, LineString, Polygon};
+use geojson::Feature;
+use rstar::{primitives::GeomWithData, RTree, RTreeObject};
+use serde::Serialize;
+
+// Vertices closer than this, or this close to the line between their neighbors, are removed
+const TOLERANCE_METERS: f64 = 0.001;
+// A vertex where the boundary turns back with less than this angle is a spike
+const SPIKE_DEGREES: f64 = 1.0;
+
+/// What `Pavement::new` changed about the input to make it valid
+#[derive(Clone, Default, PartialEq, Debug, Serialize)]
+pub struct RepairReport {
+    // Vertices at the same place as the next one
+    pub duplicate_points: usize,
+    // Vertices in the middle of a straight line
+    pub collinear_points: usize,
+    // Vertices where the boundary goes out and comes straight back
+    pub spikes: usize,
+    // Places where a ring crosses or touches itself
+    pub self_intersections: usize,
+    // Pieces of the exterior split off at self-intersections. Only the biggest piece is kept. Also
+    // counts an exterior with no area left at all, which is kept as it was.
+    pub dropped_pieces: usize,
+    // Holes with no area left
+    pub dropped_holes: usize,
+    // Rings turned around, so the exterior is counterclockwise and holes are clockwise in WGS84, as
+    // GeoJSON expects. Mercator's Y axis points down, so it's the other way around there.
+    pub reoriented_rings: usize,
+}
+
+impl RepairReport {
+    /// True if the input was already valid
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Adds every repair made as a property of a GeoJSON feature
+    pub fn set_properties(&self, f: &mut Feature) {
+        for (key, count) in [
+            ("duplicate_points", self.duplicate_points),
+            ("collinear_points", self.collinear_points),
+            ("spikes", self.spikes),
+            ("self_intersections", self.self_intersections),
+            ("dropped_pieces", self.dropped_pieces),
+            ("dropped_holes", self.dropped_holes),
+            ("reoriented_rings", self.reoriented_rings),
+        ] {
+            if count > 0 {
+                f.set_property(format!("repaired_{key}"), count);
+            }
+        }
+    }
+}
+
+/// Fixes duplicate and collinear points, spikes, self-intersections and ring orientation
+pub(crate) fn repair(polygon: &Polygon) -> (Polygon, RepairReport) {
+    let mut report = RepairReport::default();
+
+    let mut exterior = clean_ring(polygon.exterior(), &mut report);
+    if exterior.len() < 3 {
+        // Nothing sensible is left, so don't make it worse
+        report.dropped_pieces += 1;
+        return (polygon.clone(), report);
+    }
+    let mut pieces = split_self_intersections(exterior, &mut report);
+    pieces.sort_by(|a, b| ring_area(a).abs().partial_cmp(&ring_area(b).abs()).unwrap());
+    exterior = pieces.pop().unwrap().0;
+    report.dropped_pieces += pieces.len();
+    exterior.pop();
+
+    let mut interiors = Vec::new();
+    for ring in polygon.interiors() {
+        let pts = clean_ring(ring, &mut report);
+        if pts.len() < 3 {
+            report.dropped_holes += 1;
+            continue;
+        }
+        interiors.extend(split_self_intersections(pts, &mut report));
+    }
+
+    let mut exterior = LineString::new(exterior);
+    exterior.close();
+    if ring_area(&exterior) > 0.0 {
+        exterior.0.reverse();
+        report.reoriented_rings += 1;
+    }
+    for ring in &mut interiors {
+        if ring_area(ring) < 0.0 {
+            ring.0.reverse();
+            report.reoriented_rings += 1;
+        }
+    }
+    (Polygon::new(exterior, interiors), report)
+}
+
+// Returns the ring's points, without repeating the first one at the end
+fn clean_ring(ring: &LineString, report: &mut RepairReport) -> Vec<Coord> {
+    let mut pts = ring.0.clone();
+    if pts.len() > 1 && pts[0] == pts[pts.len() - 1] {
+        pts.pop();
+    }
+
+    // Removing one point can make its neighbors a problem, so repeat until nothing changes
+    let mut changed = true;
+    while changed && pts.len() >= 3 {
+        changed = false;
+        let mut idx = 0;
+        while idx < pts.len() && pts.len() >= 3 {
+            let prev = pts[(idx + pts.len() - 1) % pts.len()];
+            let pt = pts[idx];
+            let next = pts[(idx + 1) % pts.len()];
+
+            let to_prev = prev - pt;
+            let to_next = next - pt;
+            let cross = to_prev.x * to_next.y - to_prev.y * to_next.x;
+            let dot = to_prev.x * to_next.x + to_prev.y * to_next.y;
+
+            let counter = if pt.euclidean_distance(&next) < TOLERANCE_METERS {
+                &mut report.duplicate_points
+            } else if pt.euclidean_distance(&prev) < TOLERANCE_METERS {
+                // Removed when we get to prev
+                idx += 1;
+                continue;
+            } else if cross.abs().atan2(dot).to_degrees() < SPIKE_DEGREES {
+                &mut report.spikes
+            } else if dot < 0.0 && cross.abs() / prev.euclidean_distance(&next) < TOLERANCE_METERS {
+                &mut report.collinear_points
+            } else {
+                idx += 1;
+                continue;
+            };
+            *counter += 1;
+            pts.remove(idx);
+            changed = true;
+        }
+    }
+    if pts.len() < 3 {
+        return Vec::new();
+    }
+    pts
+}
+
+// Splits a ring into simple rings wherever it crosses or touches itself. Rings with no area are
+// dropped.
+fn split_self_intersections(pts: Vec<Coord>, report: &mut RepairReport) -> Vec<LineString> {
+    let n = pts.len();
+    let segment = |idx: usize| Line::new(pts[idx], pts[(idx + 1) % n]);
+    let tree: RTree<GeomWithData<rstar::primitives::Line<[f64; 2]>, usize>> = RTree::bulk_load(
+        (0..n)
+            .map(|idx| {
+                let line = segment(idx);
+                GeomWithData::new(
+                    rstar::primitives::Line::new(line.start.into(), line.end.into()),
+                    idx,
+                )
+            })
+            .collect(),
+    );
+
+    // Points to insert along each segment
+    let mut splits: Vec<Vec<Coord>> = vec![Vec::new(); n];
+    for idx1 in 0..n {
+        let line1 = segment(idx1);
+        let envelope =
+            rstar::primitives::Line::<[f64; 2]>::new(line1.start.into(), line1.end.into())
+                .envelope();
+        for obj in tree.locate_in_envelope_intersecting(&envelope) {
+            let idx2 = obj.data;
+            // Neighboring segments always touch
+            if idx2 <= idx1 || idx2 == idx1 + 1 || (idx1 == 0 && idx2 == n - 1) {
+                continue;
+            }
+            let line2 = segment(idx2);
+            let hits = match geo::algorithm::line_intersection::line_intersection(line1, line2) {
+                Some(LineIntersection::SinglePoint { intersection, .. }) => vec![intersection],
+                Some(LineIntersection::Collinear { intersection }) => {
+                    vec![intersection.start, intersection.end]
+                }
+                None => continue,
+            };
+            report.self_intersections += 1;
+            for hit in hits {
+                for (idx, line) in [(idx1, line1), (idx2, line2)] {
+                    if hit != line.start && hit != line.end {
+                        splits[idx].push(hit);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut noded = Vec::new();
+    for (idx, mut hits) in splits.into_iter().enumerate() {
+        noded.push(pts[idx]);
+        hits.sort_by(|a, b| {
+            let dist_a = pts[idx].euclidean_distance(a);
+            let dist_b = pts[idx].euclidean_distance(b);
+            dist_a.partial_cmp(&dist_b).unwrap()
+        });
+        hits.dedup();
+        noded.extend(hits);
+    }
+
+    // Every time we come back to a point we've already visited, cut off the loop since then
+    let key = |pt: &Coord| (pt.x.to_bits(), pt.y.to_bits());
+    let mut rings = Vec::new();
+    let mut current: Vec<Coord> = Vec::new();
+    let mut visited: HashMap<(u64, u64), usize> = HashMap::new();
+    for pt in noded {
+        if let Some(pos) = visited.get(&key(&pt)).cloned() {
+            let mut ring = current.split_off(pos);
+            for x in &ring[1..] {
+                visited.remove(&key(x));
+            }
+            ring.push(pt);
+            rings.push(LineString::new(ring));
+            current.push(pt);
+        } else {
+            visited.insert(key(&pt), current.len());
+            current.push(pt);
+        }
+    }
+    current.push(current[0]);
+    rings.push(LineString::new(current));
+
+    rings.retain(|ring| ring.0.len() >= 4 && ring_area(ring) != 0.0);
+    rings
+}
+
+// Positive if counterclockwise in Mercator, which is clockwise in WGS84
+fn ring_area(ring: &LineString) -> f64 {
+    Polygon::new(ring.clone(), Vec::new()).signed_area()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    // Oriented like GeoJSON expects, once Y is flipped
+    fn rectangle_with_hole() -> Polygon {
+        Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (0.0, 4.0),
+                (10.0, 4.0),
+                (10.0, 0.0),
+                (0.0, 0.0),
+            ]),
+            vec![LineString::from(vec![
+                (2.0, 1.0),
+                (3.0, 1.0),
+                (3.0, 2.0),
+                (2.0, 2.0),
+                (2.0, 1.0),
+            ])],
+        )
+    }
+
+    #[test]
+    fn valid_polygons_are_unchanged() {
+        let polygon = rectangle_with_hole();
+        assert_eq!(repair(&polygon), (polygon, RepairReport::default()));
+    }
+
+    #[test]
+    fn reorient_rings() {
+        let mut polygon = rectangle_with_hole();
+        polygon.exterior_mut(|ring| ring.0.reverse());
+        polygon.interiors_mut(|rings| rings[0].0.reverse());
+        let (repaired, report) = repair(&polygon);
+        assert_eq!(repaired, rectangle_with_hole());
+        assert_eq!(
+            report,
+            RepairReport {
+                reoriented_rings: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn geojson_orientation_is_valid() {
+        // Counterclockwise, per RFC 7946
+        let gj = r#"{"type": "Polygon", "coordinates": [[[0, 0], [0.001, 0], [0.001, 0.001], [0, 0.001], [0, 0]]]}"#;
+        let (pavements, _) = crate::utils::read_input(gj.to_string(), &Config::default()).unwrap();
+        assert!(pavements[0].repairs.is_empty());
+    }
+
+    #[test]
+    fn record_a_degenerate_exterior() {
+        let polygon = Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (0.0, 0.0)]),
+            Vec::new(),
+        );
+        let (repaired, report) = repair(&polygon);
+        assert_eq!(repaired, polygon);
+        assert!(!report.is_empty());
+        assert_eq!(report.dropped_pieces, 1);
+    }
+
+    #[test]
+    fn clean_up_points() {
+        // With a duplicate, a collinear point, and a spike
+        let polygon = Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (0.0, 4.0),
+                (0.0, 4.0),
+                (10.0, 4.0),
+                (10.0, 2.0),
+                (20.0, 2.0),
+                (10.0, 2.0),
+                (10.0, 0.0),
+                (5.0, 0.0),
+                (0.0, 0.0),
+            ]),
+            Vec::new(),
+        );
+        let (repaired, report) = repair(&polygon);
+        assert_eq!(
+            repaired.exterior(),
+            &LineString::from(vec![
+                (0.0, 0.0),
+                (0.0, 4.0),
+                (10.0, 4.0),
+                (10.0, 0.0),
+                (0.0, 0.0)
+            ])
+        );
+        assert_eq!(
+            report,
+            RepairReport {
+                // The spike also leaves its two ends at the same place
+                duplicate_points: 2,
+                // 10,2 is left in the middle of a straight line once the spike is gone
+                collinear_points: 2,
+                spikes: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn bowtie_keeps_the_bigger_half() {
+        // Crossing at 40/9, 20/9
+        let polygon = Polygon::new(
+            LineString::from(vec![
+                (0.0, 0.0),
+                (10.0, 5.0),
+                (10.0, 0.0),
+                (0.0, 4.0),
+                (0.0, 0.0),
+            ]),
+            Vec::new(),
+        );
+        let (repaired, report) = repair(&polygon);
+        assert!((repaired.unsigned_area() - 125.0 / 9.0).abs() < 1e-9);
+        assert!(ring_area(repaired.exterior()) < 0.0);
+        assert_eq!(report.self_intersections, 1);
+        assert_eq!(report.dropped_pieces, 1);
+    }
+}
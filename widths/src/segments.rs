@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use geo::{
+    Area, BooleanOps, BoundingRect, Coord, EuclideanDistance, Intersects, LineString, MultiPolygon,
+    Point, Polygon,
+};
+use rstar::{primitives::GeomWithData, primitives::Rectangle, RTree, AABB};
+
+use crate::{project_away, Config, Pavement};
+
+/// How to cut pavements into pieces in `split_pavements`
+#[derive(Clone)]
+pub struct SplitConfig {
+    // Cut out a disc around each junction of the center lines, to make separate pieces for the
+    // junction and each arm. The radius is this multiple of the distance from the junction to the
+    // boundary, so it should be a bit more than 1 to reach across the arms. None doesn't cut at
+    // junctions.
+    pub junction_radius: Option<f64>,
+    // Splitting lines are thickened to this width in meters, so each side becomes a separate piece
+    pub cut_width: f64,
+    // Drop pieces smaller than this area in m^2, like slivers next to a cut
+    pub min_area: f64,
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        Self {
+            junction_radius: Some(1.5),
+            cut_width: 0.01,
+            min_area: 1.0,
+        }
+    }
+}
+
+/// Cuts each pavement into pieces along any of `lines` crossing it, like kerbs, crossings or road
+/// center lines, and at junctions of its center lines. Each piece becomes a Pavement with the
+/// parent's source IDs and center lines, and `parent` set to the index of the pavement it came
+/// from, even if it wasn't cut. Run this before `calculate`. `cfg` is used to find the center
+/// lines and to make the new pavements. Everything must be in Mercator.
+pub fn split_pavements(
+    pavements: Vec<Pavement>,
+    lines: &[LineString],
+    split_cfg: &SplitConfig,
+    cfg: &Config,
+) -> Vec<Pavement> {
+    let tree = RTree::bulk_load(
+        lines
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, line)| {
+                let bbox = line.bounding_rect()?;
+                Some(GeomWithData::new(
+                    Rectangle::<[f64; 2]>::from_corners(bbox.min().into(), bbox.max().into()),
+                    idx,
+                ))
+            })
+            .collect(),
+    );
+
+    let mut results = Vec::new();
+    for (idx, pavement) in pavements.into_iter().enumerate() {
+        let mut pieces = vec![pavement.input.clone()];
+
+        let crossing = match pavement.input.bounding_rect() {
+            Some(bbox) => tree
+                .locate_in_envelope_intersecting(&AABB::from_corners(
+                    bbox.min().into(),
+                    bbox.max().into(),
+                ))
+                .map(|obj| &lines[obj.data])
+                .filter(|line| pavement.input.intersects(*line))
+                .collect(),
+            None => Vec::new(),
+        };
+        let cutter = thicken_lines(crossing.into_iter(), split_cfg.cut_width);
+        if !cutter.0.is_empty() {
+            pieces = MultiPolygon::new(pieces).difference(&cutter).0;
+        }
+
+        if let Some(factor) = split_cfg.junction_radius {
+            for junction in find_junctions(&pavement, cfg) {
+                let radius = factor * distance_to_boundary(&pavement.input, junction);
+                let disc = disc(junction, radius);
+                let mut next = Vec::new();
+                for piece in pieces {
+                    next.extend(piece.difference(&disc));
+                    next.extend(piece.intersection(&disc));
+                }
+                pieces = next;
+            }
+        }
+
+        for piece in pieces {
+            if piece.unsigned_area() < split_cfg.min_area {
+                continue;
+            }
+            let mut result = Pavement::new(piece, cfg);
+            result.source_ids = pavement.source_ids.clone();
+            result.centerlines = pavement.centerlines.clone();
+            result.parent = Some(idx);
+            results.push(result);
+        }
+    }
+    results
+}
+
+// Points where at least 3 center lines end, before they're joined. Center lines crossing without
+// ending there, which can only happen for provided center lines, aren't found.
+fn find_junctions(pavement: &Pavement, cfg: &Config) -> Vec<Coord> {
+    let mut cfg = cfg.clone();
+    cfg.join_skeletons = false;
+    cfg.remove_short_skeletons = None;
+    let mut tmp = Pavement::new(pavement.input.clone(), &cfg);
+    tmp.centerlines = pavement.centerlines.clone();
+    tmp.skeletonize(&cfg);
+
+    // Skeleton endpoints can be slightly off, so match them to the millimeter
+    let mut counts: HashMap<(i64, i64), (Coord, usize)> = HashMap::new();
+    for line in &tmp.skeletons {
+        for pt in [line.0[0], *line.0.last().unwrap()] {
+            let key = (
+                (pt.x * 1000.0).round() as i64,
+                (pt.y * 1000.0).round() as i64,
+            );
+            counts.entry(key).or_insert((pt, 0)).1 += 1;
+        }
+    }
+    let mut junctions: Vec<Coord> = counts
+        .into_values()
+        .filter(|(_, count)| *count >= 3)
+        .map(|(pt, _)| pt)
+        .collect();
+    // For deterministic output
+    junctions.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+    junctions
+}
+
+fn distance_to_boundary(polygon: &Polygon, pt: Coord) -> f64 {
+    let pt = Point::from(pt);
+    std::iter::once(polygon.exterior())
+        .chain(polygon.interiors())
+        .map(|ring| pt.euclidean_distance(ring))
+        .fold(f64::MAX, f64::min)
+}
+
+fn disc(center: Coord, radius: f64) -> Polygon {
+    let mut pts: Vec<Coord> = (0..32)
+        .map(|i| project_away(center, (i as f64) * 360.0 / 32.0, radius))
+        .collect();
+    pts.push(pts[0]);
+    Polygon::new(LineString::new(pts), Vec::new())
+}
+
+// Unions a thin rectangle around every segment of every line
+fn thicken_lines<'a>(lines: impl Iterator<Item = &'a LineString>, width: f64) -> MultiPolygon {
+    let mut rectangles = Vec::new();
+    for line in lines {
+        for segment in line.lines() {
+            if segment.start == segment.end {
+                continue;
+            }
+            let angle = segment.dy().atan2(segment.dx()).to_degrees();
+            let rectangle = Polygon::new(
+                LineString::new(vec![
+                    project_away(segment.start, angle - 90.0, width / 2.0),
+                    project_away(segment.end, angle - 90.0, width / 2.0),
+                    project_away(segment.end, angle + 90.0, width / 2.0),
+                    project_away(segment.start, angle + 90.0, width / 2.0),
+                    project_away(segment.start, angle - 90.0, width / 2.0),
+                ]),
+                Vec::new(),
+            );
+            rectangles.push(MultiPolygon::new(vec![rectangle]));
+        }
+    }
+    union_all(rectangles)
+}
+
+// Unions pairs, then pairs of those, and so on, so no single boolean op gets huge
+fn union_all(mut polygons: Vec<MultiPolygon>) -> MultiPolygon {
+    while polygons.len() > 1 {
+        let mut next = Vec::new();
+        let mut iter = polygons.into_iter();
+        while let Some(first) = iter.next() {
+            next.push(match iter.next() {
+                Some(second) => first.union(&second),
+                None => first,
+            });
+        }
+        polygons = next;
+    }
+    polygons
+        .pop()
+        .unwrap_or_else(|| MultiPolygon::new(Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assign_centerlines;
+
+    // A 30x4 horizontal bar, with a 4m wide stem going up from the middle
+    fn t_shape() -> Polygon {
+        Polygon::new(
+            LineString::from(vec![
+                (0.0, -2.0),
+                (30.0, -2.0),
+                (30.0, 2.0),
+                (17.0, 2.0),
+                (17.0, 20.0),
+                (13.0, 20.0),
+                (13.0, 2.0),
+                (0.0, 2.0),
+                (0.0, -2.0),
+            ]),
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn cut_by_lines() {
+        let cfg = Config::default();
+        let split_cfg = SplitConfig {
+            junction_radius: None,
+            ..Default::default()
+        };
+        let pavements = vec![Pavement::new(t_shape(), &cfg)];
+        // A crossing over the left arm, and a line that misses
+        let lines = vec![
+            LineString::from(vec![(5.0, -10.0), (5.0, 10.0)]),
+            LineString::from(vec![(50.0, -10.0), (50.0, 10.0)]),
+        ];
+        let pieces = split_pavements(pavements, &lines, &split_cfg, &cfg);
+
+        let mut areas: Vec<f64> = pieces.iter().map(|p| p.polygon.unsigned_area()).collect();
+        areas.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(areas.len(), 2);
+        assert!((areas[0] - 20.0).abs() < 0.1);
+        assert!((areas[1] - 172.0).abs() < 0.1);
+        assert!(pieces.iter().all(|p| p.parent == Some(0)));
+    }
+
+    #[test]
+    fn union_overlapping_rectangles() {
+        let rectangles = [0.0, 5.0, 10.0]
+            .into_iter()
+            .map(|x1| {
+                MultiPolygon::new(vec![Polygon::new(
+                    LineString::from(vec![
+                        (x1, 0.0),
+                        (x1 + 10.0, 0.0),
+                        (x1 + 10.0, 1.0),
+                        (x1, 1.0),
+                        (x1, 0.0),
+                    ]),
+                    Vec::new(),
+                )])
+            })
+            .collect();
+        let union = union_all(rectangles);
+        assert_eq!(union.0.len(), 1);
+        assert!((union.unsigned_area() - 20.0).abs() < 1e-9);
+        assert!(union_all(Vec::new()).0.is_empty());
+    }
+
+    #[test]
+    fn cut_at_junctions() {
+        let cfg = Config::default();
+        let mut pavements = vec![Pavement::new(t_shape(), &cfg)];
+        pavements[0].source_ids = vec!["a".to_string()];
+        assign_centerlines(
+            &mut pavements,
+            &[
+                LineString::from(vec![(0.0, 0.0), (15.0, 0.0)]),
+                LineString::from(vec![(15.0, 0.0), (30.0, 0.0)]),
+                LineString::from(vec![(15.0, 0.0), (15.0, 20.0)]),
+            ],
+        );
+        let pieces = split_pavements(pavements, &[], &SplitConfig::default(), &cfg);
+
+        // The junction and 3 arms
+        assert_eq!(pieces.len(), 4);
+        for mut piece in pieces {
+            assert_eq!(piece.source_ids, vec!["a"]);
+            assert_eq!(piece.parent, Some(0));
+            piece.calculate(&cfg);
+            assert!(!piece.skeletons.is_empty());
+        }
+    }
+}
@@ -1,43 +1,148 @@
-use geo::{Coord, LineLocatePoint, LineSplit, LineString};
+use geo::{
+    Coord, EuclideanLength, Line, LineInterpolatePoint, LineLocatePoint, LineSplit, LineString,
+};
 
-/// Splits the line every time the width changes by some granularity. Returns the min and max width
-/// of each split.
+use crate::{CenterWithWidth, Sample, WidthBands, WidthCategory};
+
+/// Splits the line every time the width changes by some granularity or crosses into a different
+/// band. Returns the min and max width of each split. `centerline` is the index of `linestring`,
+/// to record on each result.
 pub fn split(
     linestring: &LineString,
-    thickened_points: Vec<(Coord, f64)>,
+    centerline: usize,
+    samples: &[Sample],
     width_granularity: f64,
-) -> Vec<(LineString, f64, f64)> {
+    bands: &WidthBands,
+) -> Vec<CenterWithWidth> {
     let mut result = Vec::new();
+    for (category, run) in split_by_category(linestring, samples, bands) {
+        split_by_granularity(
+            linestring,
+            centerline,
+            &run,
+            width_granularity,
+            category,
+            &mut result,
+        );
+    }
+    result
+}
+
+/// Groups consecutive samples with the same category. Where the width crosses a threshold between
+/// two samples, an interpolated sample exactly at the threshold ends one group and starts the
+/// next.
+fn split_by_category(
+    linestring: &LineString,
+    samples: &[Sample],
+    bands: &WidthBands,
+) -> Vec<(WidthCategory, Vec<Sample>)> {
+    let mut groups = Vec::new();
+    let mut category = bands.categorize(samples[0].width());
+    let mut group = vec![samples[0]];
+
+    for pair in samples.windows(2) {
+        let (width1, width2) = (pair[0].width(), pair[1].width());
+        // A big jump might cross more than one threshold. Thresholds are increasing, so reverse
+        // them if the width is decreasing.
+        let mut crossings: Vec<f64> = bands
+            .thresholds()
+            .into_iter()
+            .filter(|threshold| (width1 < *threshold) != (width2 < *threshold))
+            .map(|threshold| (threshold - width1) / (width2 - width1))
+            .collect();
+        if width2 < width1 {
+            crossings.reverse();
+        }
 
+        for (idx, fraction) in crossings.iter().enumerate() {
+            let crossing = interpolate(linestring, &pair[0], &pair[1], *fraction);
+            group.push(crossing);
+            groups.push((category, std::mem::take(&mut group)));
+            group.push(crossing);
+
+            // The category right after the crossing
+            let next_fraction = crossings.get(idx + 1).cloned().unwrap_or(1.0);
+            category =
+                bands.categorize(width1 + (width2 - width1) * (fraction + next_fraction) / 2.0);
+        }
+        group.push(pair[1]);
+    }
+    groups.push((category, group));
+
+    groups
+}
+
+fn interpolate(
+    linestring: &LineString,
+    sample1: &Sample,
+    sample2: &Sample,
+    fraction: f64,
+) -> Sample {
+    let lerp = |x1: f64, x2: f64| x1 + fraction * (x2 - x1);
+    let lerp_pt = |pt1: Coord, pt2: Coord| Coord {
+        x: lerp(pt1.x, pt2.x),
+        y: lerp(pt1.y, pt2.y),
+    };
+    let dist_along = lerp(sample1.dist_along, sample2.dist_along);
+    let pt = linestring
+        .line_interpolate_point(dist_along / linestring.euclidean_length())
+        .map(|pt| pt.into())
+        .unwrap_or_else(|| lerp_pt(sample1.pt, sample2.pt));
+
+    Sample {
+        pt,
+        angle: sample1.angle,
+        dist_along,
+        perp: Line::new(
+            lerp_pt(sample1.perp.start, sample2.perp.start),
+            lerp_pt(sample1.perp.end, sample2.perp.end),
+        ),
+        left: lerp(sample1.left, sample2.left),
+        right: lerp(sample1.right, sample2.right),
+        unbounded: sample1.unbounded || sample2.unbounded,
+    }
+}
+
+fn split_by_granularity(
+    linestring: &LineString,
+    centerline: usize,
+    samples: &[Sample],
+    width_granularity: f64,
+    category: WidthCategory,
+    result: &mut Vec<CenterWithWidth>,
+) {
     let mut idx1 = 0;
-    while idx1 < thickened_points.len() - 1 {
+    while idx1 < samples.len() - 1 {
         let mut idx2 = idx1 + 1;
-        while (thickened_points[idx1].1 - thickened_points[idx2].1).abs() <= width_granularity
-            && idx2 < thickened_points.len() - 1
+        while (samples[idx1].width() - samples[idx2].width()).abs() <= width_granularity
+            && idx2 < samples.len() - 1
         {
             idx2 += 1;
         }
-        if let Some(sliced) = slice(
-            linestring,
-            thickened_points[idx1].0,
-            thickened_points[idx2].0,
-        ) {
-            let min = thickened_points[idx1..=idx2]
-                .iter()
-                .map(|pair| pair.1)
-                .min_by_key(|w| (w * 1000.0) as usize)
-                .unwrap();
-            let max = thickened_points[idx1..=idx2]
-                .iter()
-                .map(|pair| pair.1)
-                .max_by_key(|w| (w * 1000.0) as usize)
-                .unwrap();
-            result.push((sliced, min, max));
+        if let Some(sliced) = slice(linestring, samples[idx1].pt, samples[idx2].pt) {
+            let group = &samples[idx1..=idx2];
+            result.push(CenterWithWidth {
+                linestring: sliced,
+                centerline,
+                start_chainage: samples[idx1].dist_along,
+                end_chainage: samples[idx2].dist_along,
+                min_width: min(group.iter().map(|s| s.width())),
+                max_width: max(group.iter().map(|s| s.width())),
+                min_left: min(group.iter().map(|s| s.offsets().0)),
+                min_right: min(group.iter().map(|s| s.offsets().1)),
+                category,
+            });
         }
         idx1 = idx2;
     }
+}
 
-    result
+fn min<I: Iterator<Item = f64>>(values: I) -> f64 {
+    values.min_by_key(|x| (x * 1000.0) as usize).unwrap()
+}
+
+fn max<I: Iterator<Item = f64>>(values: I) -> f64 {
+    values.max_by_key(|x| (x * 1000.0) as usize).unwrap()
 }
 
 fn slice(linestring: &LineString, pt1: Coord, pt2: Coord) -> Option<LineString> {
@@ -50,4 +155,77 @@ fn slice(linestring: &LineString, pt1: Coord, pt2: Coord) -> Option<LineString>
     result.into_second()
 }
 
-// TODO Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summarize(pieces: Vec<CenterWithWidth>) -> Vec<(f64, f64, f64, f64, WidthCategory)> {
+        pieces
+            .into_iter()
+            .map(|x| {
+                (
+                    x.start_chainage,
+                    x.end_chainage,
+                    x.min_width,
+                    x.max_width,
+                    x.category,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_by_granularity() {
+        let ls = LineString::from(vec![(0.0, 0.0), (20.0, 0.0)]);
+        let samples = vec![
+            Sample::heading_east(0.0, 1.0),
+            Sample::heading_east(5.0, 1.1),
+            Sample::heading_east(10.0, 1.2),
+            Sample::heading_east(15.0, 3.0),
+            Sample::heading_east(20.0, 3.1),
+        ];
+        // Put every width in the same band
+        let bands = WidthBands::new(0.0, 100.0).unwrap();
+        assert_eq!(
+            summarize(split(&ls, 0, &samples, 0.5, &bands)),
+            vec![
+                (0.0, 15.0, 1.0, 3.0, WidthCategory::Acceptable),
+                (15.0, 20.0, 3.0, 3.1, WidthCategory::Acceptable),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_by_bands() {
+        let ls = LineString::from(vec![(0.0, 0.0), (40.0, 0.0)]);
+        let samples = vec![
+            Sample::heading_east(0.0, 1.0),
+            Sample::heading_east(10.0, 1.0),
+            Sample::heading_east(20.0, 3.0),
+            Sample::heading_east(30.0, 1.8),
+            Sample::heading_east(40.0, 1.0),
+        ];
+        let bands = WidthBands::new(1.5, 2.0).unwrap();
+        // Use a big granularity, so only the bands matter
+        let result = summarize(split(&ls, 0, &samples, 10.0, &bands));
+        let categories: Vec<WidthCategory> = result.iter().map(|x| x.4).collect();
+        assert_eq!(
+            categories,
+            vec![
+                WidthCategory::BelowMinimum,
+                WidthCategory::Acceptable,
+                WidthCategory::Desirable,
+                WidthCategory::Acceptable,
+                WidthCategory::BelowMinimum,
+            ]
+        );
+
+        // The width crosses 1.5 a quarter of the way from 10 to 20
+        assert!((result[0].1 - 12.5).abs() < 1e-6);
+        assert!((result[0].3 - 1.5).abs() < 1e-6);
+        // And crosses 2.0 halfway
+        assert!((result[1].1 - 15.0).abs() < 1e-6);
+        assert!((result[2].2 - 2.0).abs() < 1e-6);
+        assert!((result[2].3 - 3.0).abs() < 1e-6);
+    }
+}
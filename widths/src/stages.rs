@@ -0,0 +1,149 @@
+use crate::Config;
+
+/// The steps of `Pavement::calculate`. Each stage's results depend on some config settings and on
+/// the results of earlier stages, so changing a setting only needs to rerun the stage using it and
+/// the stages downstream of that.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Stage {
+    /// Derive `polygon` from the input
+    RemoveHoles,
+    /// Find `skeletons`
+    Skeletonize,
+    /// Measure `samples` and make `thickened_lines`
+    MakePerpLines,
+    /// Split the skeletons into `center_with_width`
+    SplitLines,
+    /// Find `pinch_points`
+    PinchPoints,
+    /// Fit `cross_sections`
+    CrossSections,
+    /// Summarize `stats`
+    Stats,
+}
+
+impl Stage {
+    /// Every stage, in an order where upstream stages come first
+    pub const ALL: [Stage; 7] = [
+        Stage::RemoveHoles,
+        Stage::Skeletonize,
+        Stage::MakePerpLines,
+        Stage::SplitLines,
+        Stage::PinchPoints,
+        Stage::CrossSections,
+        Stage::Stats,
+    ];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Stage::RemoveHoles => "remove_holes",
+            Stage::Skeletonize => "skeletonize",
+            Stage::MakePerpLines => "make_perp_lines",
+            Stage::SplitLines => "split_lines",
+            Stage::PinchPoints => "pinch_points",
+            Stage::CrossSections => "cross_sections",
+            Stage::Stats => "stats",
+        }
+    }
+
+    /// The stages whose results this one reads
+    pub fn upstream(self) -> &'static [Stage] {
+        match self {
+            Stage::RemoveHoles => &[],
+            Stage::Skeletonize => &[Stage::RemoveHoles],
+            Stage::MakePerpLines => &[Stage::Skeletonize],
+            Stage::SplitLines => &[Stage::MakePerpLines],
+            Stage::PinchPoints => &[Stage::MakePerpLines],
+            Stage::CrossSections => &[Stage::SplitLines],
+            Stage::Stats => &[Stage::MakePerpLines],
+        }
+    }
+
+    /// Does any setting this stage uses differ between the two configs?
+    pub fn config_changed(self, old: &Config, new: &Config) -> bool {
+        match self {
+            Stage::RemoveHoles => old.remove_holes != new.remove_holes,
+            Stage::Skeletonize => {
+                old.filter_skeletons_outside != new.filter_skeletons_outside
+                    || old.filter_skeletons_near_boundary != new.filter_skeletons_near_boundary
+                    || old.join_skeletons != new.join_skeletons
+                    || old.remove_short_skeletons != new.remove_short_skeletons
+            }
+            Stage::MakePerpLines => {
+                old.make_perps_step_size != new.make_perps_step_size
+                    || old.max_perp_length != new.max_perp_length
+                    || old.width_method != new.width_method
+                    || old.perp_midpoint_ratio != new.perp_midpoint_ratio
+                    || old.min_chord_angle_step != new.min_chord_angle_step
+            }
+            Stage::SplitLines => {
+                old.width_granularity != new.width_granularity || old.width_bands != new.width_bands
+            }
+            Stage::PinchPoints => {
+                old.pinch_point_prominence != new.pinch_point_prominence
+                    || old.pinch_point_window != new.pinch_point_window
+            }
+            Stage::CrossSections => old.lane_widths != new.lane_widths,
+            Stage::Stats => {
+                old.stats_thresholds != new.stats_thresholds || old.width_bands != new.width_bands
+            }
+        }
+    }
+
+    /// The stages that have to run to bring results calculated with `old` up-to-date with `new`,
+    /// in the order to run them. Everything runs if there's no `old`.
+    pub fn stale(old: Option<&Config>, new: &Config) -> Vec<Stage> {
+        let mut stale: Vec<Stage> = Vec::new();
+        for stage in Stage::ALL {
+            let changed = match old {
+                Some(old) => stage.config_changed(old, new),
+                None => true,
+            };
+            if changed || stage.upstream().iter().any(|x| stale.contains(x)) {
+                stale.push(stage);
+            }
+        }
+        stale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WidthBands;
+
+    #[test]
+    fn only_downstream_stages_are_stale() {
+        let old = Config::default();
+        assert_eq!(Stage::stale(None, &old), Stage::ALL.to_vec());
+        assert!(Stage::stale(Some(&old), &Config::default()).is_empty());
+
+        let mut new = Config::default();
+        new.width_granularity = 1.0;
+        assert_eq!(
+            Stage::stale(Some(&old), &new),
+            vec![Stage::SplitLines, Stage::CrossSections]
+        );
+
+        let mut new = Config::default();
+        new.width_bands = WidthBands::new(1.0, 2.0).unwrap();
+        assert_eq!(
+            Stage::stale(Some(&old), &new),
+            vec![Stage::SplitLines, Stage::CrossSections, Stage::Stats]
+        );
+
+        let mut new = Config::default();
+        new.pinch_point_window = 10.0;
+        assert_eq!(Stage::stale(Some(&old), &new), vec![Stage::PinchPoints]);
+
+        let mut new = Config::default();
+        new.make_perps_step_size = Some(2.0);
+        assert_eq!(
+            Stage::stale(Some(&old), &new),
+            Stage::ALL[Stage::MakePerpLines as usize..].to_vec()
+        );
+
+        let mut new = Config::default();
+        new.remove_holes = None;
+        assert_eq!(Stage::stale(Some(&old), &new), Stage::ALL.to_vec());
+    }
+}
@@ -0,0 +1,218 @@
+use geo::EuclideanLength;
+use geojson::Feature;
+use serde::Serialize;
+
+use crate::{Config, Pavement, WidthBands, WidthCategory};
+
+/// Summarizes how the width varies along all of a pavement's center lines
+#[derive(Serialize)]
+pub struct PavementStats {
+    // Total length of all skeletons, in meters
+    pub centerline_length: f64,
+
+    // Over all samples
+    pub min_width: f64,
+    pub max_width: f64,
+    pub mean_width: f64,
+    pub median_width: f64,
+
+    // (percentile, width), weighted by the length of center line between samples
+    pub percentiles: Vec<(usize, f64)>,
+
+    // (threshold, length of center line narrower than the threshold)
+    pub length_below: Vec<(f64, f64)>,
+
+    // Length of center line in each width band
+    pub length_per_category: Vec<(WidthCategory, f64)>,
+}
+
+const PERCENTILES: [usize; 5] = [10, 25, 50, 75, 90];
+
+impl PavementStats {
+    pub fn new(pavement: &Pavement, cfg: &Config) -> Option<Self> {
+        let mut widths: Vec<f64> = pavement
+            .samples
+            .iter()
+            .flatten()
+            .map(|s| s.width())
+            .collect();
+
+        // Between each pair of consecutive samples, assume the width changes linearly
+        let mut intervals = Vec::new();
+        for samples in &pavement.samples {
+            for pair in samples.windows(2) {
+                intervals.push(Interval {
+                    length: pair[1].dist_along - pair[0].dist_along,
+                    width1: pair[0].width(),
+                    width2: pair[1].width(),
+                });
+            }
+        }
+        if intervals.is_empty() {
+            return None;
+        }
+
+        widths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // For an odd number of samples, these are the same middle value
+        let median_width = (widths[(widths.len() - 1) / 2] + widths[widths.len() / 2]) / 2.0;
+
+        let percentiles = weighted_percentiles(&mut intervals);
+
+        let sum_length_below =
+            |threshold: f64| -> f64 { intervals.iter().map(|i| i.length_below(threshold)).sum() };
+        let length_below = cfg
+            .stats_thresholds
+            .iter()
+            .map(|threshold| (*threshold, sum_length_below(*threshold)))
+            .collect();
+
+        let length_per_category = length_per_category(&intervals, &cfg.width_bands);
+
+        Some(Self {
+            centerline_length: pavement
+                .skeletons
+                .iter()
+                .map(|ls| ls.euclidean_length())
+                .sum(),
+            min_width: widths[0],
+            max_width: *widths.last().unwrap(),
+            mean_width: widths.iter().sum::<f64>() / (widths.len() as f64),
+            median_width,
+            percentiles,
+            length_below,
+            length_per_category,
+        })
+    }
+
+    /// Adds all stats as properties of a GeoJSON feature
+    pub fn set_properties(&self, f: &mut Feature) {
+        f.set_property("centerline_length", self.centerline_length);
+        f.set_property("min_width", self.min_width);
+        f.set_property("max_width", self.max_width);
+        f.set_property("mean_width", self.mean_width);
+        f.set_property("median_width", self.median_width);
+        for (pct, width) in &self.percentiles {
+            f.set_property(format!("p{pct}_width"), *width);
+        }
+        for (threshold, length) in &self.length_below {
+            f.set_property(format!("length_below_{threshold}m"), *length);
+        }
+        for (category, length) in &self.length_per_category {
+            f.set_property(format!("length_{}", category.key()), *length);
+        }
+    }
+}
+
+/// The width between two samples, assumed to change linearly
+pub(crate) struct Interval {
+    pub length: f64,
+    pub width1: f64,
+    pub width2: f64,
+}
+
+/// Each of PERCENTILES, weighted by the length of each interval. Sorts the intervals by width.
+pub(crate) fn weighted_percentiles(intervals: &mut [Interval]) -> Vec<(usize, f64)> {
+    intervals.sort_by(|a, b| a.mean_width().partial_cmp(&b.mean_width()).unwrap());
+    let total_length: f64 = intervals.iter().map(|i| i.length).sum();
+    PERCENTILES
+        .into_iter()
+        .map(|pct| {
+            let target = total_length * (pct as f64) / 100.0;
+            let mut sum = 0.0;
+            let width = intervals
+                .iter()
+                .find(|i| {
+                    sum += i.length;
+                    sum >= target
+                })
+                .unwrap_or(intervals.last().unwrap())
+                .mean_width();
+            (pct, width)
+        })
+        .collect()
+}
+
+// The length of center line in each band, matching WidthBands::categorize
+fn length_per_category(intervals: &[Interval], bands: &WidthBands) -> Vec<(WidthCategory, f64)> {
+    let sum_length_below =
+        |threshold: f64| -> f64 { intervals.iter().map(|i| i.length_below(threshold)).sum() };
+    let total_length: f64 = intervals.iter().map(|i| i.length).sum();
+    let below_minimum = sum_length_below(bands.minimum());
+    let below_desirable = sum_length_below(bands.desirable());
+    vec![
+        (WidthCategory::BelowMinimum, below_minimum),
+        (WidthCategory::Acceptable, below_desirable - below_minimum),
+        (WidthCategory::Desirable, total_length - below_desirable),
+    ]
+}
+
+impl Interval {
+    pub fn mean_width(&self) -> f64 {
+        (self.width1 + self.width2) / 2.0
+    }
+
+    fn length_below(&self, threshold: f64) -> f64 {
+        let min = self.width1.min(self.width2);
+        let max = self.width1.max(self.width2);
+        if max < threshold {
+            self.length
+        } else if min >= threshold {
+            0.0
+        } else {
+            self.length * (threshold - min) / (max - min)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(length: f64, width1: f64, width2: f64) -> Interval {
+        Interval {
+            length,
+            width1,
+            width2,
+        }
+    }
+
+    #[test]
+    fn length_below_interpolates() {
+        for i in [interval(10.0, 1.0, 3.0), interval(10.0, 3.0, 1.0)] {
+            assert_eq!(i.length_below(0.5), 0.0);
+            assert_eq!(i.length_below(1.5), 2.5);
+            assert_eq!(i.length_below(2.0), 5.0);
+            assert_eq!(i.length_below(4.0), 10.0);
+        }
+        // A constant width is either entirely below or not
+        assert_eq!(interval(10.0, 2.0, 2.0).length_below(2.0), 0.0);
+        assert_eq!(interval(10.0, 2.0, 2.0).length_below(2.1), 10.0);
+    }
+
+    #[test]
+    fn percentiles_are_weighted_by_length() {
+        let mut intervals = vec![
+            interval(60.0, 3.0, 3.0),
+            interval(10.0, 1.0, 1.0),
+            interval(30.0, 2.0, 2.0),
+        ];
+        assert_eq!(
+            weighted_percentiles(&mut intervals),
+            vec![(10, 1.0), (25, 2.0), (50, 3.0), (75, 3.0), (90, 3.0)]
+        );
+    }
+
+    #[test]
+    fn length_in_each_band() {
+        let intervals = vec![interval(10.0, 1.0, 3.0), interval(10.0, 3.0, 3.0)];
+        let bands = WidthBands::new(1.5, 2.0).unwrap();
+        assert_eq!(
+            length_per_category(&intervals, &bands),
+            vec![
+                (WidthCategory::BelowMinimum, 2.5),
+                (WidthCategory::Acceptable, 2.5),
+                (WidthCategory::Desirable, 15.0),
+            ]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use geo::{Coord, EuclideanLength, Line, LineInterpolatePoint, LineString};
+use geo::{Coord, EuclideanLength, Line, LineString};
 
 // TODO Upstream to geo
 
@@ -6,41 +6,289 @@ use geo::{Coord, EuclideanLength, Line, LineInterpolatePoint, LineString};
 /// degrees. This can't use
 /// https://docs.rs/geo/latest/geo/algorithm/line_interpolate_point/trait.LineInterpolatePoint.html
 /// because the line / angle isn't returned.
-pub fn step_along_line(linestring: &LineString, interval: f64) -> Vec<(Coord, f64)> {
-    // TODO This is very inefficient; it keeps searching from the start of the whole linestring
-    let mut result = Vec::new();
-    let mut dist_along = 0.0;
-    let length = linestring.euclidean_length();
-    while dist_along < length {
-        result.push(dist_along_linestring(linestring, dist_along));
-        dist_along += interval;
-    }
-    result
+///
+/// The result is a lazy iterator that walks each line of the linestring once. Use its methods to
+/// start partway along, include the very end, or walk backwards. If `interval` isn't positive, it
+/// yields nothing.
+pub fn step_along_line(linestring: &LineString, interval: f64) -> StepAlongLine<'_> {
+    StepAlongLine {
+        linestring,
+        interval,
+        length: linestring.euclidean_length(),
+        start_offset: 0.0,
+        include_end: false,
+        reverse: false,
+        count: 0,
+        seg: 0,
+        seg_start: 0.0,
+        last_nonempty: None,
+        // NaN isn't positive either
+        done: interval.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater),
+    }
+}
+
+/// One point found by stepping along a linestring
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub pt: Coord,
+    // The angle of the line at pt in degrees, pointing in the direction of travel
+    pub angle: f64,
+    // Distance from the first point of the linestring to pt, even when walking in reverse
+    pub dist_along: f64,
+    // Index of the line containing pt, as in `linestring.lines()`
+    pub segment: usize,
+}
+
+pub struct StepAlongLine<'a> {
+    linestring: &'a LineString,
+    interval: f64,
+    length: f64,
+    start_offset: f64,
+    include_end: bool,
+    reverse: bool,
+
+    // How many steps have been taken
+    count: usize,
+    // The current line, counting in the direction of travel, and the distance traveled to its
+    // start
+    seg: usize,
+    seg_start: f64,
+    // The last line with nonzero length seen so far, and the distance traveled to its start
+    last_nonempty: Option<(usize, f64)>,
+    done: bool,
 }
 
-fn dist_along_linestring(linestring: &LineString, dist: f64) -> (Coord, f64) {
-    let mut dist_left = dist;
-    for line in linestring.lines() {
-        let length = line.euclidean_length();
-        if length == 0.0 {
-            continue;
+impl<'a> StepAlongLine<'a> {
+    /// Skip this distance before the first step
+    pub fn start_offset(mut self, offset: f64) -> Self {
+        self.start_offset = offset;
+        self
+    }
+
+    /// Finish with the last point of the linestring, even if it's less than an interval after
+    /// the previous step
+    pub fn include_end(mut self, include_end: bool) -> Self {
+        self.include_end = include_end;
+        self
+    }
+
+    /// Start from the last point of the linestring and walk backwards
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    fn num_lines(&self) -> usize {
+        self.linestring.0.len().saturating_sub(1)
+    }
+
+    // Index into linestring.lines()
+    fn line_idx(&self, seg: usize) -> usize {
+        if self.reverse {
+            self.num_lines() - 1 - seg
+        } else {
+            seg
+        }
+    }
+
+    // Oriented in the direction of travel
+    fn line(&self, seg: usize) -> Line {
+        let idx = self.line_idx(seg);
+        let (pt1, pt2) = (self.linestring.0[idx], self.linestring.0[idx + 1]);
+        if self.reverse {
+            Line::new(pt2, pt1)
+        } else {
+            Line::new(pt1, pt2)
+        }
+    }
+
+    fn step(&self, seg: usize, pt: Coord, traveled: f64) -> Step {
+        Step {
+            pt,
+            angle: line_angle_degrees(self.line(seg)),
+            dist_along: if self.reverse {
+                (self.length - traveled).max(0.0)
+            } else {
+                traveled
+            },
+            segment: self.line_idx(seg),
         }
-        if dist_left <= length {
-            return (dist_along_line(line, dist_left), line_angle_degrees(line));
+    }
+
+    // Moves to the next line with nonzero length containing `traveled`, or past the last line
+    fn advance_to(&mut self, traveled: f64) {
+        while self.seg < self.num_lines() {
+            let length = self.line(self.seg).euclidean_length();
+            if length > 0.0 {
+                self.last_nonempty = Some((self.seg, self.seg_start));
+                if traveled <= self.seg_start + length {
+                    return;
+                }
+            }
+            self.seg_start += length;
+            self.seg += 1;
         }
-        dist_left -= length;
     }
-    // If there's leftover, it's just a small epsilon
-    let line = linestring.lines().last().unwrap();
-    (line.end, line_angle_degrees(line))
 }
 
-fn dist_along_line(line: Line, dist: f64) -> Coord {
-    line.line_interpolate_point(dist / line.euclidean_length())
-        .unwrap()
-        .into()
+impl<'a> Iterator for StepAlongLine<'a> {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        if self.done {
+            return None;
+        }
+
+        let traveled = self.start_offset + (self.count as f64) * self.interval;
+        if traveled < self.length {
+            self.advance_to(traveled);
+            // If we went past the last line, the leftover is just a small epsilon
+            let (seg, seg_start) = self.last_nonempty.unwrap();
+            let line = self.line(seg);
+            let pct = ((traveled - seg_start) / line.euclidean_length()).clamp(0.0, 1.0);
+            self.count += 1;
+            return Some(self.step(seg, line.start + line.delta() * pct, traveled));
+        }
+
+        self.done = true;
+        if !self.include_end || self.length == 0.0 || self.start_offset > self.length {
+            return None;
+        }
+        self.advance_to(f64::INFINITY);
+        let (seg, _) = self.last_nonempty.unwrap();
+        Some(self.step(seg, self.line(seg).end, self.length))
+    }
 }
 
 fn line_angle_degrees(line: Line) -> f64 {
     line.dy().atan2(line.dx()).to_degrees()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pts_and_angles(steps: StepAlongLine) -> Vec<(Coord, f64)> {
+        steps.map(|step| (step.pt, step.angle)).collect()
+    }
+
+    #[test]
+    fn straight_line() {
+        let ls = LineString::from(vec![(0.0, 0.0), (10.0, 0.0)]);
+        let result = pts_and_angles(step_along_line(&ls, 3.0));
+        assert_eq!(
+            result,
+            vec![
+                (Coord { x: 0.0, y: 0.0 }, 0.0),
+                (Coord { x: 3.0, y: 0.0 }, 0.0),
+                (Coord { x: 6.0, y: 0.0 }, 0.0),
+                (Coord { x: 9.0, y: 0.0 }, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_steps_without_a_positive_interval() {
+        let ls = LineString::from(vec![(0.0, 0.0), (10.0, 0.0)]);
+        for interval in [0.0, -1.0, f64::NAN] {
+            assert_eq!(step_along_line(&ls, interval).include_end(true).count(), 0);
+        }
+    }
+
+    #[test]
+    fn around_a_corner() {
+        let ls = LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let result = pts_and_angles(step_along_line(&ls, 5.0));
+        assert_eq!(
+            result,
+            vec![
+                (Coord { x: 0.0, y: 0.0 }, 0.0),
+                (Coord { x: 5.0, y: 0.0 }, 0.0),
+                (Coord { x: 10.0, y: 0.0 }, 0.0),
+                (Coord { x: 10.0, y: 5.0 }, 90.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_empty_segments() {
+        let ls = LineString::from(vec![(0.0, 0.0), (0.0, 0.0), (0.0, 4.0)]);
+        let result = pts_and_angles(step_along_line(&ls, 2.0));
+        assert_eq!(
+            result,
+            vec![
+                (Coord { x: 0.0, y: 0.0 }, 90.0),
+                (Coord { x: 0.0, y: 2.0 }, 90.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn chainage_and_segments() {
+        let ls = LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let result: Vec<(f64, usize)> = step_along_line(&ls, 4.0)
+            .map(|step| (step.dist_along, step.segment))
+            .collect();
+        assert_eq!(
+            result,
+            vec![(0.0, 0), (4.0, 0), (8.0, 0), (12.0, 1), (16.0, 1)]
+        );
+    }
+
+    #[test]
+    fn start_offset_and_end() {
+        let ls = LineString::from(vec![(0.0, 0.0), (10.0, 0.0)]);
+        let result = pts_and_angles(
+            step_along_line(&ls, 4.0)
+                .start_offset(1.0)
+                .include_end(true),
+        );
+        assert_eq!(
+            result,
+            vec![
+                (Coord { x: 1.0, y: 0.0 }, 0.0),
+                (Coord { x: 5.0, y: 0.0 }, 0.0),
+                (Coord { x: 9.0, y: 0.0 }, 0.0),
+                (Coord { x: 10.0, y: 0.0 }, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn reverse() {
+        let ls = LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let result: Vec<Step> = step_along_line(&ls, 8.0)
+            .reverse(true)
+            .include_end(true)
+            .collect();
+        assert_eq!(
+            result,
+            vec![
+                Step {
+                    pt: Coord { x: 10.0, y: 10.0 },
+                    angle: -90.0,
+                    dist_along: 20.0,
+                    segment: 1,
+                },
+                Step {
+                    pt: Coord { x: 10.0, y: 2.0 },
+                    angle: -90.0,
+                    dist_along: 12.0,
+                    segment: 1,
+                },
+                Step {
+                    pt: Coord { x: 4.0, y: 0.0 },
+                    angle: 180.0,
+                    dist_along: 4.0,
+                    segment: 0,
+                },
+                Step {
+                    pt: Coord { x: 0.0, y: 0.0 },
+                    angle: 180.0,
+                    dist_along: 0.0,
+                    segment: 0,
+                },
+            ]
+        );
+    }
+}
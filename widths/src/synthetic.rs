@@ -0,0 +1,353 @@
+use geo::{Coord, EuclideanDistance, EuclideanLength, LineLocatePoint, LineString, Point, Polygon};
+
+use crate::{Config, Pavement};
+
+/// A generated pavement where the true width is known everywhere along its center line
+pub struct SyntheticCase {
+    pub name: String,
+    pub centerline: LineString,
+    pub polygon: Polygon,
+    // (distance along the center line, distance to the left boundary, distance to the right
+    // boundary) at every vertex of the center line
+    profile: Vec<(f64, f64, f64)>,
+    // Ranges along the center line where the width isn't well-defined, like around corners and
+    // holes. Samples there aren't scored.
+    excluded: Vec<(f64, f64)>,
+    // Evaluate with Config::remove_holes off, so holes are measured around instead of removed
+    keep_holes: bool,
+}
+
+/// How far measured widths are from the true widths of a SyntheticCase
+pub struct Evaluation {
+    // Number of samples that were scored
+    pub samples: usize,
+    // Measured minus true width. Positive means widths are overestimated.
+    pub mean_error: f64,
+    pub mean_abs_error: f64,
+    pub rmse: f64,
+    pub max_abs_error: f64,
+    // Total skeleton length over the center line length. Low means parts of the pavement weren't
+    // measured at all; high means there are spurious branches.
+    pub skeleton_length_ratio: f64,
+}
+
+impl SyntheticCase {
+    /// Builds a polygon around `centerline`. `half_widths` returns the distance to the left and
+    /// right boundary at some distance along the center line. The center line is split into
+    /// pieces no longer than `step` meters, so the boundary follows the width profile closely.
+    pub fn new(
+        name: &str,
+        centerline: LineString,
+        half_widths: impl Fn(f64) -> (f64, f64),
+        step: f64,
+    ) -> Self {
+        let centerline = densify(&centerline, step);
+        let pts = &centerline.0;
+
+        let mut profile = Vec::new();
+        let mut left_side = Vec::new();
+        let mut right_side = Vec::new();
+        let mut dist_along = 0.0;
+        for i in 0..pts.len() {
+            if i > 0 {
+                dist_along += pts[i - 1].euclidean_distance(&pts[i]);
+            }
+            let (left, right) = half_widths(dist_along);
+            profile.push((dist_along, left, right));
+
+            // Offset along the bisector of the two lines meeting here, scaled so the boundary
+            // stays parallel to both lines at corners
+            let incoming = (i > 0).then(|| unit(pts[i] - pts[i - 1]));
+            let outgoing = (i + 1 < pts.len()).then(|| unit(pts[i + 1] - pts[i]));
+            let (dir, scale) = match (incoming, outgoing) {
+                (Some(a), Some(b)) => {
+                    let bisector = unit(a + b);
+                    (bisector, 1.0 / (bisector.x * b.x + bisector.y * b.y))
+                }
+                (Some(a), None) => (a, 1.0),
+                (None, Some(b)) => (b, 1.0),
+                (None, None) => panic!("center line of {name} needs at least two points"),
+            };
+            // Left is angle - 90. Mercator's Y axis points down.
+            let normal = Coord {
+                x: dir.y,
+                y: -dir.x,
+            };
+            left_side.push(pts[i] + normal * (left * scale));
+            right_side.push(pts[i] - normal * (right * scale));
+        }
+
+        let mut exterior = left_side;
+        exterior.extend(right_side.into_iter().rev());
+        exterior.push(exterior[0]);
+
+        Self {
+            name: name.to_string(),
+            polygon: Polygon::new(LineString::new(exterior), Vec::new()),
+            centerline,
+            profile,
+            excluded: Vec::new(),
+            keep_holes: false,
+        }
+    }
+
+    /// A straight pavement with constant width
+    pub fn straight(length: f64, width: f64) -> Self {
+        Self::new(
+            "straight",
+            line(vec![(0.0, 0.0), (length, 0.0)]),
+            |_| (width / 2.0, width / 2.0),
+            1.0,
+        )
+    }
+
+    /// A pavement with constant width following a circular arc
+    pub fn curved(radius: f64, sweep_degrees: f64, width: f64) -> Self {
+        let num_pts = sweep_degrees.abs().ceil() as usize + 1;
+        let centerline = LineString::from(
+            (0..num_pts)
+                .map(|i| {
+                    let angle = (sweep_degrees * (i as f64) / ((num_pts - 1) as f64)).to_radians();
+                    (radius * angle.cos(), radius * angle.sin())
+                })
+                .collect::<Vec<_>>(),
+        );
+        Self::new("curved", centerline, |_| (width / 2.0, width / 2.0), 1.0)
+    }
+
+    /// Two straight legs meeting at a right angle. The width around the corner isn't scored.
+    pub fn l_shape(leg: f64, width: f64) -> Self {
+        let mut case = Self::new(
+            "l_shape",
+            line(vec![(0.0, 0.0), (leg, 0.0), (leg, leg)]),
+            |_| (width / 2.0, width / 2.0),
+            1.0,
+        );
+        case.excluded.push((leg - width, leg + width));
+        case
+    }
+
+    /// A straight pavement whose width changes linearly from one end to the other
+    pub fn tapering(length: f64, start_width: f64, end_width: f64) -> Self {
+        Self::new(
+            "tapering",
+            line(vec![(0.0, 0.0), (length, 0.0)]),
+            |dist| {
+                let width = start_width + (end_width - start_width) * dist / length;
+                (width / 2.0, width / 2.0)
+            },
+            1.0,
+        )
+    }
+
+    /// A straight pavement with a rectangular bite taken out of the left side in the middle, like
+    /// a street furniture build-out. The edges of the notch aren't scored.
+    pub fn notch(length: f64, width: f64, notch_length: f64, notch_depth: f64) -> Self {
+        let start = (length - notch_length) / 2.0;
+        let end = start + notch_length;
+        let mut case = Self::new(
+            "notch",
+            line(vec![(0.0, 0.0), (length, 0.0)]),
+            |dist| {
+                if dist > start && dist < end {
+                    (width / 2.0 - notch_depth, width / 2.0)
+                } else {
+                    (width / 2.0, width / 2.0)
+                }
+            },
+            0.5,
+        );
+        case.excluded.push((start - 1.0, start + 1.0));
+        case.excluded.push((end - 1.0, end + 1.0));
+        case
+    }
+
+    /// A straight pavement with a round hole in the middle, like a tree pit. The width around the
+    /// hole isn't scored. The hole is kept however small it is, ignoring `Config::remove_holes`.
+    pub fn with_hole(length: f64, width: f64, hole_radius: f64) -> Self {
+        let mut case = Self::new(
+            "with_hole",
+            line(vec![(0.0, 0.0), (length, 0.0)]),
+            |_| (width / 2.0, width / 2.0),
+            1.0,
+        );
+        let center = Coord {
+            x: length / 2.0,
+            y: 0.0,
+        };
+        let hole = LineString::from(
+            (0..=32)
+                .map(|i| {
+                    let angle = (360.0 * (i as f64) / 32.0_f64).to_radians();
+                    center
+                        + Coord {
+                            x: hole_radius * angle.cos(),
+                            y: hole_radius * angle.sin(),
+                        }
+                })
+                .collect::<Vec<_>>(),
+        );
+        case.polygon.interiors_push(hole);
+        case.keep_holes = true;
+        case.excluded.push((
+            length / 2.0 - hole_radius - width,
+            length / 2.0 + hole_radius + width,
+        ));
+        case
+    }
+
+    /// A fixed suite covering common shapes
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::straight(100.0, 3.0),
+            Self::curved(30.0, 120.0, 3.0),
+            Self::l_shape(50.0, 2.5),
+            Self::tapering(100.0, 4.0, 1.5),
+            Self::notch(100.0, 3.0, 10.0, 1.0),
+            Self::with_hole(100.0, 4.0, 0.75),
+        ]
+    }
+
+    /// The true width at some distance along the center line
+    pub fn true_width(&self, dist_along: f64) -> f64 {
+        let idx = self
+            .profile
+            .partition_point(|(dist, _, _)| *dist < dist_along);
+        if idx == 0 {
+            let (_, left, right) = self.profile[0];
+            return left + right;
+        }
+        if idx == self.profile.len() {
+            let (_, left, right) = self.profile[idx - 1];
+            return left + right;
+        }
+        let (dist1, left1, right1) = self.profile[idx - 1];
+        let (dist2, left2, right2) = self.profile[idx];
+        let pct = (dist_along - dist1) / (dist2 - dist1);
+        (left1 + right1) + pct * ((left2 + right2) - (left1 + right1))
+    }
+
+    /// Runs the full calculation on this case and compares every sample with the true width at
+    /// the closest point on the center line. Returns None if no samples could be scored.
+    pub fn evaluate(&self, cfg: &Config) -> Option<Evaluation> {
+        let mut cfg = cfg.clone();
+        if self.keep_holes {
+            cfg.remove_holes = None;
+        }
+        let mut pavement = Pavement::new(self.polygon.clone(), &cfg);
+        pavement.calculate(&cfg);
+
+        let length = self.centerline.euclidean_length();
+        let mut errors = Vec::new();
+        for sample in pavement.samples.iter().flatten() {
+            let fraction = self
+                .centerline
+                .line_locate_point(&Point::from(sample.pt))
+                .unwrap();
+            let dist_along = fraction * length;
+            if self
+                .excluded
+                .iter()
+                .any(|(start, end)| dist_along >= *start && dist_along <= *end)
+            {
+                continue;
+            }
+            errors.push(sample.width() - self.true_width(dist_along));
+        }
+        if errors.is_empty() {
+            return None;
+        }
+
+        let n = errors.len() as f64;
+        let skeleton_length: f64 = pavement
+            .skeletons
+            .iter()
+            .map(|ls| ls.euclidean_length())
+            .sum();
+        Some(Evaluation {
+            samples: errors.len(),
+            mean_error: errors.iter().sum::<f64>() / n,
+            mean_abs_error: errors.iter().map(|e| e.abs()).sum::<f64>() / n,
+            rmse: (errors.iter().map(|e| e * e).sum::<f64>() / n).sqrt(),
+            max_abs_error: errors.iter().map(|e| e.abs()).fold(0.0, f64::max),
+            skeleton_length_ratio: skeleton_length / length,
+        })
+    }
+}
+
+fn line(pts: Vec<(f64, f64)>) -> LineString {
+    LineString::from(pts)
+}
+
+fn unit(v: Coord) -> Coord {
+    v / v.x.hypot(v.y)
+}
+
+// Adds points so no line is longer than step, keeping all the original points
+fn densify(linestring: &LineString, step: f64) -> LineString {
+    let mut pts = vec![linestring.0[0]];
+    for line in linestring.lines() {
+        let pieces = (line.euclidean_length() / step).ceil().max(1.0) as usize;
+        for i in 1..=pieces {
+            let pct = (i as f64) / (pieces as f64);
+            pts.push(line.start + line.delta() * pct);
+        }
+    }
+    LineString::new(pts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Area;
+
+    #[test]
+    fn straight_is_a_rectangle() {
+        let case = SyntheticCase::straight(100.0, 3.0);
+        assert!((case.polygon.unsigned_area() - 300.0).abs() < 1e-6);
+        assert_eq!(case.true_width(50.0), 3.0);
+    }
+
+    #[test]
+    fn l_shape_keeps_width_around_the_corner() {
+        let case = SyntheticCase::l_shape(50.0, 2.0);
+        // Two 50x2 legs overlapping in a 1x1 square at the outside of the corner and missing one
+        // at the inside
+        assert!((case.polygon.unsigned_area() - 200.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tapering_width_is_interpolated() {
+        let case = SyntheticCase::tapering(100.0, 4.0, 2.0);
+        assert!((case.true_width(25.0) - 3.5).abs() < 1e-9);
+        assert!((case.true_width(100.0) - 2.0).abs() < 1e-9);
+        assert!((case.polygon.unsigned_area() - 300.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn notch_narrows_one_side() {
+        let case = SyntheticCase::notch(100.0, 3.0, 10.0, 1.0);
+        assert_eq!(case.true_width(10.0), 3.0);
+        assert_eq!(case.true_width(50.0), 2.0);
+    }
+
+    #[test]
+    fn evaluate_straight() {
+        let eval = SyntheticCase::straight(100.0, 3.0)
+            .evaluate(&Config::default())
+            .unwrap();
+        assert!(eval.samples > 10);
+        assert!(eval.mean_abs_error < 0.05);
+        assert!((eval.skeleton_length_ratio - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn evaluate_with_hole() {
+        let eval = SyntheticCase::with_hole(100.0, 4.0, 0.75)
+            .evaluate(&Config::default())
+            .unwrap();
+        assert!(eval.samples > 10);
+        assert!(eval.mean_abs_error < 0.1);
+        assert!((eval.skeleton_length_ratio - 1.0).abs() < 0.2);
+    }
+}
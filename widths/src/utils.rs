@@ -1,27 +1,167 @@
 use anyhow::{bail, Result};
-use geo::{Geometry, GeometryCollection, Polygon};
-use geojson::GeoJson;
+use geo::{Geometry, GeometryCollection, LineString, Polygon};
+use geojson::{feature::Id, Feature, GeoJson, JsonValue};
+use geozero::wkb::{Ewkb, Wkb};
+use geozero::ToGeo;
+use wkt::TryFromWkt;
 
 use crate::{Config, Mercator, Pavement};
 
-pub fn read_gj_input(input: String, cfg: &Config) -> Result<(Vec<Pavement>, Mercator)> {
+/// Reads polygons and multipolygons in WGS84 from any format `read_polygons` detects. Each
+/// Pavement's source_ids has the ID of the feature it came from, or the index of its geometry in
+/// the input if it has no ID.
+pub fn read_input(input: String, cfg: &Config) -> Result<(Vec<Pavement>, Mercator)> {
+    let (wgs84_polygons, source_ids) = read_polygons(&input)?;
+    Ok(with_source_ids(wgs84_polygons, source_ids, cfg))
+}
+
+/// Like `read_input`, for one binary WKB or EWKB geometry
+pub fn read_wkb_input(input: &[u8], cfg: &Config) -> Result<(Vec<Pavement>, Mercator)> {
+    let (wgs84_polygons, source_ids) = read_wkb_polygons(input)?;
+    Ok(with_source_ids(wgs84_polygons, source_ids, cfg))
+}
+
+/// Reads polygons and multipolygons in WGS84, along with a source ID for each: the ID of the
+/// feature each came from, or the index of its geometry in the input. Detects the format:
+/// GeoJSON (a FeatureCollection, Feature or Geometry), hex-encoded WKB or EWKB, or WKT. The text
+/// formats may have one geometry per line. Other geometry types are skipped with a warning, but
+/// it's an error if there are no polygons at all.
+pub fn read_polygons(input: &str) -> Result<(Vec<Polygon>, Vec<String>)> {
+    let mut polygons = Polygons::default();
+    for (geometry, id, _) in read_geometries(input, None)? {
+        polygons.add(geometry, &id, None);
+    }
+    polygons.finish()
+}
+
+/// Reads one property of the features `read_polygons` reads, with one value per polygon in the
+/// same order, for `merge_pavements`. Values that aren't strings are written as JSON, and missing
+/// values are None. Only GeoJSON has properties, so the other formats are an error.
+pub fn read_property(input: &str, key: &str) -> Result<Vec<Option<String>>> {
+    if !input.trim_start().starts_with('{') {
+        bail!("Properties can only be read from GeoJSON, not WKT or WKB");
+    }
+    let mut polygons = Polygons::default();
+    for (geometry, id, value) in read_geometries(input, Some(key))? {
+        polygons.add(geometry, &id, value);
+    }
+    Ok(polygons.values)
+}
+
+/// Reads polygons and multipolygons from one binary WKB or EWKB geometry. They all get the ID
+/// "0".
+pub fn read_wkb_polygons(input: &[u8]) -> Result<(Vec<Polygon>, Vec<String>)> {
+    let mut polygons = Polygons::default();
+    polygons.add(read_wkb(input)?, "0", None);
+    polygons.finish()
+}
+
+/// Reads linestrings and multilinestrings in WGS84, with source IDs like `read_polygons`, for
+/// `assign_centerlines` or `conflate`. Other geometry types are skipped with a warning.
+pub fn read_linestrings(input: &str) -> Result<(Vec<LineString>, Vec<String>)> {
+    let mut lines = Vec::new();
+    let mut source_ids = Vec::new();
+    for (geometry, id, _) in read_geometries(input, None)? {
+        add_linestrings(geometry, &id, &mut lines, &mut source_ids);
+    }
+    if lines.is_empty() {
+        bail!("No lines in the input");
+    }
+    Ok((lines, source_ids))
+}
+
+// Detects the format and returns every geometry with its ID, and the value of the `key` property
+// if there is one
+fn read_geometries(
+    input: &str,
+    key: Option<&str>,
+) -> Result<Vec<(Geometry, String, Option<String>)>> {
+    let input = input.trim();
+    let geometries = if input.starts_with('{') {
+        return read_gj_geometries(input, key);
+    } else if input
+        .chars()
+        .all(|c| c.is_ascii_hexdigit() || c.is_ascii_whitespace())
+    {
+        read_hex_wkb_geometries(input)?
+    } else {
+        read_wkt_geometries(input)?
+    };
+    Ok(geometries
+        .into_iter()
+        .map(|(geometry, id)| (geometry, id, None))
+        .collect())
+}
+
+fn read_gj_geometries(
+    input: &str,
+    key: Option<&str>,
+) -> Result<Vec<(Geometry, String, Option<String>)>> {
     let gj: GeoJson = input.parse()?;
-    let mut wgs84_polygons = Vec::new();
-    for x in geojson::quick_collection(&gj)? {
-        match x {
-            Geometry::Polygon(p) => {
-                wgs84_polygons.push(p);
-            }
-            Geometry::MultiPolygon(mp) => {
-                for p in mp {
-                    wgs84_polygons.push(p);
-                }
-            }
-            _ => bail!("Unexpected geometry type {:?}", x),
-        }
+    let features = match gj {
+        GeoJson::FeatureCollection(fc) => fc.features,
+        GeoJson::Feature(f) => vec![f],
+        GeoJson::Geometry(g) => vec![Feature::from(g)],
+    };
+
+    let mut results = Vec::new();
+    for (idx, feature) in features.into_iter().enumerate() {
+        let value = key
+            .and_then(|key| feature.property(key))
+            .map(|value| match value {
+                JsonValue::String(x) => x.clone(),
+                x => x.to_string(),
+            });
+        let id = match feature.id {
+            Some(Id::String(id)) => id,
+            Some(Id::Number(id)) => id.to_string(),
+            None => idx.to_string(),
+        };
+        let Some(geometry) = feature.geometry else {
+            continue;
+        };
+        results.push((Geometry::try_from(geometry)?, id, value));
+    }
+    Ok(results)
+}
+
+// EWKT's `SRID=...;` prefix is ignored
+fn read_wkt_geometries(input: &str) -> Result<Vec<(Geometry, String)>> {
+    let geometries: Result<Vec<Geometry>> = input
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(parse_wkt)
+        .collect();
+    match geometries {
+        Ok(geometries) => Ok(geometries
+            .into_iter()
+            .enumerate()
+            .map(|(idx, geometry)| (geometry, idx.to_string()))
+            .collect()),
+        // A single geometry may be spread over many lines
+        Err(err) => match parse_wkt(input) {
+            Ok(geometry) => Ok(vec![(geometry, "0".to_string())]),
+            Err(_) => Err(err),
+        },
     }
+}
 
-    Ok(to_mercator(wgs84_polygons, cfg))
+fn read_hex_wkb_geometries(input: &str) -> Result<Vec<(Geometry, String)>> {
+    let mut results = Vec::new();
+    for (idx, line) in input.lines().filter(|l| !l.trim().is_empty()).enumerate() {
+        results.push((read_wkb(&hex::decode(line.trim())?)?, idx.to_string()));
+    }
+    Ok(results)
+}
+
+// Reads one geometry from ISO WKB or PostGIS's EWKB. Z and M coordinates are dropped, and an SRID
+// is ignored, since the input is assumed to be WGS84 anyway.
+fn read_wkb(bytes: &[u8]) -> Result<Geometry> {
+    match Wkb(bytes.to_vec()).to_geo() {
+        Ok(geometry) => Ok(geometry),
+        // EWKB sets flags in the geometry type that ISO WKB doesn't allow
+        Err(_) => Ok(Ewkb(bytes.to_vec()).to_geo()?),
+    }
 }
 
 pub fn to_mercator(polygons: Vec<Polygon>, cfg: &Config) -> (Vec<Pavement>, Mercator) {
@@ -37,3 +177,216 @@ pub fn to_mercator(polygons: Vec<Polygon>, cfg: &Config) -> (Vec<Pavement>, Merc
 
     (results, mercator)
 }
+
+fn with_source_ids(
+    polygons: Vec<Polygon>,
+    source_ids: Vec<String>,
+    cfg: &Config,
+) -> (Vec<Pavement>, Mercator) {
+    let (mut pavements, mercator) = to_mercator(polygons, cfg);
+    for (pavement, id) in pavements.iter_mut().zip(source_ids) {
+        pavement.source_ids = vec![id];
+    }
+    (pavements, mercator)
+}
+
+fn parse_wkt(input: &str) -> Result<Geometry> {
+    let mut input = input.trim();
+    if input.starts_with("SRID=") {
+        if let Some((_, rest)) = input.split_once(';') {
+            input = rest;
+        }
+    }
+    match Geometry::try_from_wkt_str(input) {
+        Ok(geometry) => Ok(geometry),
+        Err(err) => bail!("Invalid WKT: {err}"),
+    }
+}
+
+// Collects polygons with their source IDs, from any type of geometry
+#[derive(Default)]
+struct Polygons {
+    polygons: Vec<Polygon>,
+    source_ids: Vec<String>,
+    // The value of a property for each polygon, from read_property
+    values: Vec<Option<String>>,
+}
+
+impl Polygons {
+    fn add(&mut self, geometry: Geometry, id: &str, value: Option<String>) {
+        match geometry {
+            Geometry::Polygon(p) if p.exterior().0.is_empty() => {
+                log::warn!("Skipping an empty Polygon from input {id}");
+            }
+            Geometry::Polygon(p) => {
+                self.polygons.push(p);
+                self.source_ids.push(id.to_string());
+                self.values.push(value);
+            }
+            Geometry::MultiPolygon(mp) => {
+                for p in mp {
+                    self.add(Geometry::Polygon(p), id, value.clone());
+                }
+            }
+            Geometry::GeometryCollection(gc) => {
+                for g in gc {
+                    self.add(g, id, value.clone());
+                }
+            }
+            x => log::warn!("Skipping {} from input {id}", geometry_type(&x)),
+        }
+    }
+
+    fn finish(self) -> Result<(Vec<Polygon>, Vec<String>)> {
+        if self.polygons.is_empty() {
+            bail!("No polygons in the input");
+        }
+        Ok((self.polygons, self.source_ids))
+    }
+}
+
+fn add_linestrings(
+    geometry: Geometry,
+    id: &str,
+    lines: &mut Vec<LineString>,
+    source_ids: &mut Vec<String>,
+) {
+    match geometry {
+        Geometry::Line(line) => {
+            lines.push(line.into());
+            source_ids.push(id.to_string());
+        }
+        Geometry::LineString(ls) => {
+            lines.push(ls);
+            source_ids.push(id.to_string());
+        }
+        Geometry::MultiLineString(mls) => {
+            for ls in mls {
+                lines.push(ls);
+                source_ids.push(id.to_string());
+            }
+        }
+        Geometry::GeometryCollection(gc) => {
+            for g in gc {
+                add_linestrings(g, id, lines, source_ids);
+            }
+        }
+        x => log::warn!("Skipping {} from input {id}", geometry_type(&x)),
+    }
+}
+
+fn geometry_type(geometry: &Geometry) -> &'static str {
+    match geometry {
+        Geometry::Point(_) => "a Point",
+        Geometry::Line(_) => "a Line",
+        Geometry::LineString(_) => "a LineString",
+        Geometry::Polygon(_) => "a Polygon",
+        Geometry::MultiPoint(_) => "a MultiPoint",
+        Geometry::MultiLineString(_) => "a MultiLineString",
+        Geometry::MultiPolygon(_) => "a MultiPolygon",
+        Geometry::GeometryCollection(_) => "a GeometryCollection",
+        Geometry::Rect(_) => "a Rect",
+        Geometry::Triangle(_) => "a Triangle",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{MultiPoint, Point};
+
+    #[test]
+    fn little_endian_polygon() {
+        // ST_AsBinary('POLYGON((0 0, 1 0, 1 1, 0 0))')
+        let bytes = hex::decode(
+            "010300000001000000040000000000000000000000000000000000000000000000\
+             0000F03F0000000000000000000000000000F03F000000000000F03F0000000000\
+             0000000000000000000000",
+        )
+        .unwrap();
+        assert_eq!(
+            read_wkb(&bytes).unwrap(),
+            Geometry::Polygon(Polygon::new(
+                LineString::from(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)]),
+                Vec::new()
+            ))
+        );
+    }
+
+    #[test]
+    fn ewkb_with_srid_and_z() {
+        // ST_AsEWKB('SRID=4326;MULTIPOINT Z ((1 2 3))'), big-endian
+        let bytes = hex::decode(
+            "00A0000004000010E6000000010080000001\
+             3FF000000000000040000000000000004008000000000000",
+        )
+        .unwrap();
+        assert_eq!(
+            read_wkb(&bytes).unwrap(),
+            Geometry::MultiPoint(MultiPoint(vec![Point::new(1.0, 2.0)]))
+        );
+    }
+
+    #[test]
+    fn truncated_wkb() {
+        assert!(read_polygons("0103000000010000000400").is_err());
+        assert!(read_polygons("010").is_err());
+    }
+
+    #[test]
+    fn formats_agree() {
+        let gj = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "id": "a", "properties": {}, "geometry": {"type": "Point", "coordinates": [0, 0]}},
+            {"type": "Feature", "properties": {}, "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}}
+        ]}"#;
+        let wkt = "POINT (0 0)\nSRID=4326;POLYGON ((0 0, 1 0, 1 1, 0 0))";
+        let hex = "010100000000000000000000000000000000000000\n\
+                   0103000000010000000400000000000000000000000000000000000000000000000000F03F0000000000000000000000000000F03F000000000000F03F00000000000000000000000000000000";
+
+        let (polygons, ids) = read_polygons(gj).unwrap();
+        assert_eq!(ids, vec!["1"]);
+        assert_eq!(read_polygons(wkt).unwrap(), (polygons.clone(), ids.clone()));
+        assert_eq!(read_polygons(hex).unwrap(), (polygons, ids));
+
+        assert!(read_polygons("POINT (0 0)").is_err());
+    }
+
+    #[test]
+    fn skip_empty_polygons() {
+        let (polygons, ids) =
+            read_polygons("POLYGON EMPTY\nPOLYGON ((0 0, 1 0, 1 1, 0 0))").unwrap();
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(ids, vec!["1"]);
+        // A WKB polygon with no rings
+        assert!(read_polygons("010300000000000000").is_err());
+    }
+
+    #[test]
+    fn linestrings() {
+        let (lines, ids) =
+            read_linestrings("LINESTRING (0 0, 1 1)\nMULTILINESTRING ((2 2, 3 3), (4 4, 5 5))")
+                .unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(ids, vec!["0", "1", "1"]);
+        assert!(read_linestrings("POLYGON ((0 0, 1 0, 1 1, 0 0))").is_err());
+    }
+
+    #[test]
+    fn property_per_polygon() {
+        let gj = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"kind": 3}, "geometry": {"type": "MultiPolygon", "coordinates": [[[[0, 0], [1, 0], [1, 1], [0, 0]]], [[[2, 0], [3, 0], [3, 1], [2, 0]]]]}},
+            {"type": "Feature", "properties": {}, "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}},
+            {"type": "Feature", "properties": {"kind": "footway"}, "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}}
+        ]}"#;
+        assert_eq!(
+            read_property(gj, "kind").unwrap(),
+            vec![
+                Some("3".to_string()),
+                Some("3".to_string()),
+                None,
+                Some("footway".to_string())
+            ]
+        );
+        assert!(read_property("POLYGON ((0 0, 1 0, 1 1, 0 0))", "kind").is_err());
+    }
+}
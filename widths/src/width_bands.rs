@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+/// Thresholds for classifying width against some design standard, like the 2m clear footway from
+/// Inclusive Mobility or cycle lane widths from LTN 1/20
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "UncheckedWidthBands")]
+pub struct WidthBands {
+    // At most desirable, checked by new
+    minimum: f64,
+    desirable: f64,
+}
+
+#[derive(Deserialize)]
+struct UncheckedWidthBands {
+    minimum: f64,
+    desirable: f64,
+}
+
+impl TryFrom<UncheckedWidthBands> for WidthBands {
+    type Error = String;
+
+    fn try_from(bands: UncheckedWidthBands) -> Result<Self, Self::Error> {
+        Self::new(bands.minimum, bands.desirable)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WidthCategory {
+    BelowMinimum,
+    Acceptable,
+    Desirable,
+}
+
+impl WidthBands {
+    /// Fails if `minimum` is more than `desirable`
+    pub fn new(minimum: f64, desirable: f64) -> Result<Self, String> {
+        if minimum > desirable {
+            return Err(format!(
+                "width_bands.minimum ({minimum}) must be at most width_bands.desirable ({desirable})"
+            ));
+        }
+        Ok(Self { minimum, desirable })
+    }
+
+    pub fn minimum(&self) -> f64 {
+        self.minimum
+    }
+
+    pub fn desirable(&self) -> f64 {
+        self.desirable
+    }
+
+    pub fn categorize(&self, width: f64) -> WidthCategory {
+        if width < self.minimum {
+            WidthCategory::BelowMinimum
+        } else if width < self.desirable {
+            WidthCategory::Acceptable
+        } else {
+            WidthCategory::Desirable
+        }
+    }
+
+    /// The widths where the category changes, in increasing order
+    pub fn thresholds(&self) -> [f64; 2] {
+        [self.minimum, self.desirable]
+    }
+}
+
+impl WidthCategory {
+    /// A name to use in output properties
+    pub fn key(self) -> &'static str {
+        match self {
+            WidthCategory::BelowMinimum => "below_minimum",
+            WidthCategory::Acceptable => "acceptable",
+            WidthCategory::Desirable => "desirable",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bands_must_be_in_order() {
+        let bands: WidthBands =
+            serde_json::from_str(r#"{"minimum": 1.5, "desirable": 2.0}"#).unwrap();
+        assert_eq!(bands.thresholds(), [1.5, 2.0]);
+        assert!(
+            serde_json::from_str::<WidthBands>(r#"{"minimum": 2.0, "desirable": 1.5}"#).is_err()
+        );
+        assert!(WidthBands::new(2.0, 1.5).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use geo::EuclideanLength;
+use serde::Serialize;
+
+use crate::Pavement;
+
+/// The width along one center line, for charting
+#[derive(Serialize)]
+pub struct WidthProfile {
+    // Index into Pavement::skeletons
+    pub centerline: usize,
+    // The length of the center line in meters
+    pub length: f64,
+    // Ordered by chainage
+    pub points: Vec<ProfilePoint>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+pub struct ProfilePoint {
+    // Index into Pavement::samples[centerline]
+    pub sample: usize,
+    // Distance from the start of the center line in meters
+    pub chainage: f64,
+    pub width: f64,
+    pub left: f64,
+    pub right: f64,
+    pub unbounded: bool,
+}
+
+impl Pavement {
+    /// Returns one profile per center line, including ones without any samples
+    pub fn width_profiles(&self) -> Vec<WidthProfile> {
+        self.skeletons
+            .iter()
+            .zip(&self.samples)
+            .enumerate()
+            .map(|(centerline, (skeleton, samples))| WidthProfile {
+                centerline,
+                length: skeleton.euclidean_length(),
+                points: samples
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, sample)| ProfilePoint {
+                        sample: idx,
+                        chainage: sample.dist_along,
+                        width: sample.width(),
+                        left: sample.left,
+                        right: sample.right,
+                        unbounded: sample.unbounded,
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
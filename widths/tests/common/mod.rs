@@ -0,0 +1,26 @@
+//! Shared between the golden tests and benchmarks
+
+use anyhow::Result;
+
+/// Pulls the test cases from the web app, formatted as alternating lines of `"name":` and
+/// `"WKT",`
+pub fn read_test_cases() -> Result<Vec<(String, String)>> {
+    let contents = std::fs::read_to_string(format!(
+        "{}/../web/src/test_cases.ts",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let mut results = Vec::new();
+    let mut name = None;
+    for line in contents.lines() {
+        let Some(line) = line.trim().strip_prefix('"') else {
+            continue;
+        };
+        if let Some(x) = line.strip_suffix("\":") {
+            name = Some(x.to_string());
+        } else if let Some(x) = line.strip_suffix("\",") {
+            results.push((name.take().unwrap(), x.to_string()));
+        }
+    }
+    assert!(!results.is_empty(), "No test cases found");
+    Ok(results)
+}
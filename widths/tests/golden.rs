@@ -0,0 +1,89 @@
+//! Runs every test case from the web app and compares widths to snapshots in tests/golden/. When
+//! output changes on purpose, review the diff and regenerate with `UPDATE_GOLDEN=1 cargo test`.
+
+use anyhow::{anyhow, Result};
+use geo::Polygon;
+use geojson::{Feature, FeatureCollection};
+use widths::{utils::to_mercator, Config};
+use wkt::TryFromWkt;
+
+mod common;
+
+#[test]
+fn golden() -> Result<()> {
+    let dir = format!("{}/tests/golden", env!("CARGO_MANIFEST_DIR"));
+    std::fs::create_dir_all(&dir)?;
+    let update = std::env::var("UPDATE_GOLDEN").is_ok();
+
+    let mut changed = Vec::new();
+    let mut missing = Vec::new();
+    for (name, wkt) in common::read_test_cases()? {
+        let actual = calculate(&wkt)?;
+        let path = format!("{dir}/{}.geojson", name.replace(' ', "_"));
+
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            _ if update => {
+                println!("Writing {path}");
+                std::fs::write(&path, actual)?;
+            }
+            Ok(_) => changed.push(name),
+            Err(_) => missing.push(name),
+        }
+    }
+
+    let mut problems = Vec::new();
+    if !changed.is_empty() {
+        problems.push(format!("Output changed for {}", changed.join(", ")));
+    }
+    if !missing.is_empty() {
+        problems.push(format!("No snapshot for {}", missing.join(", ")));
+    }
+    if !problems.is_empty() {
+        panic!(
+            "{}. If this is expected, rerun with UPDATE_GOLDEN=1 and commit the snapshots",
+            problems.join(". ")
+        );
+    }
+    Ok(())
+}
+
+/// Returns GeoJSON with each perpendicular line and center_with_width piece, rounding widths to
+/// the centimeter
+fn calculate(wkt: &str) -> Result<String> {
+    let cfg = Config::default();
+    let polygon = Polygon::try_from_wkt_str(wkt).map_err(|err| anyhow!("{err}"))?;
+    let (pavements, mercator) = to_mercator(vec![polygon], &cfg);
+
+    let mut features = Vec::new();
+    for mut pavement in pavements {
+        pavement.calculate(&cfg);
+
+        for sample in pavement.samples.iter().flatten() {
+            let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&sample.perp)));
+            f.set_property("layer", "perp");
+            f.set_property("left", round(sample.left));
+            f.set_property("right", round(sample.right));
+            features.push(f);
+        }
+        for x in &pavement.center_with_width {
+            let mut f = Feature::from(geojson::Geometry::from(&mercator.to_wgs84(&x.linestring)));
+            f.set_property("layer", "center_with_width");
+            f.set_property("min_width", round(x.min_width));
+            f.set_property("max_width", round(x.max_width));
+            f.set_property("category", x.category.key());
+            features.push(f);
+        }
+    }
+
+    let fc = FeatureCollection {
+        features,
+        bbox: None,
+        foreign_members: None,
+    };
+    Ok(format!("{}\n", serde_json::to_string_pretty(&fc)?))
+}
+
+fn round(x: f64) -> f64 {
+    (x * 100.0).round() / 100.0
+}